@@ -3,8 +3,10 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, remove_schemas, schema_for};
 
-use quartier::msg::{CountResponse, HandleMsg, InitMsg, QueryMsg};
-use quartier::state::State;
+use quartier::msg::{
+    ConfigResponse, HandleAnswer, HandleMsg, InitMsg, ListPollsResponse, MiniPublicResponse,
+    MyBallotResponse, PollResponse, QueryMsg, ResultResponse, StandingDelegateResponse, TallyResponse,
+};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -15,6 +17,13 @@ fn main() {
     export_schema(&schema_for!(InitMsg), &out_dir);
     export_schema(&schema_for!(HandleMsg), &out_dir);
     export_schema(&schema_for!(QueryMsg), &out_dir);
-    export_schema(&schema_for!(State), &out_dir);
-    export_schema(&schema_for!(CountResponse), &out_dir);
+    export_schema(&schema_for!(HandleAnswer), &out_dir);
+    export_schema(&schema_for!(PollResponse), &out_dir);
+    export_schema(&schema_for!(ListPollsResponse), &out_dir);
+    export_schema(&schema_for!(TallyResponse), &out_dir);
+    export_schema(&schema_for!(ResultResponse), &out_dir);
+    export_schema(&schema_for!(StandingDelegateResponse), &out_dir);
+    export_schema(&schema_for!(MyBallotResponse), &out_dir);
+    export_schema(&schema_for!(MiniPublicResponse), &out_dir);
+    export_schema(&schema_for!(ConfigResponse), &out_dir);
 }
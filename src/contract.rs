@@ -1,17 +1,38 @@
-use std::fmt::Debug;
-
 use cosmwasm_std::{
-    to_binary, Api, Env, Extern, HandleResponse, HandleResult, InitResponse, InitResult, Querier,
-    QueryResult, StdError, StdResult, Storage,
+    to_binary, Api, Decimal, Env, Extern, HandleResponse, HandleResult, InitResponse, InitResult, Querier,
+    QueryResult, StdResult, Storage,
 };
-use serde::{Deserialize, Serialize};
-
-use crate::msg::{InitMsg, HandleMsg, QueryMsg, HandleAnswer, ResponseStatus::{Failure, Success},};
-use crate::state::{Tally, Ballot};
-use std::collections::HashSet;
-use cosmwasm_std::{HumanAddr,};
-
 
+use crate::error::ContractError;
+use crate::msg::{InitMsg, HandleMsg, QueryMsg, HandleAnswer, PollResponse, ListPollsResponse, PollStatus, TallyResponse, OptionTally, ResultResponse, Outcome, Funding, OptionScores, MiniPublicResponse, StandingDelegateResponse, TopicDelegate, MyBallotResponse, ConfigResponse, QueryWithPermit, ResponseStatus::{Failure, Success},};
+use crate::state::{
+    Tally, Ballot, Vote, OptionVotes, Config, MiniPublic, Draw, Poll, Quorum, Threshold, VotingMethod, RankedVotes, Runoff, save_config, load_config, save_poll, load_poll,
+    save_tally, may_load_tally, save_mini_public, may_load_mini_public, add_member, remove_member, is_member,
+    save_ballot, may_load_ballot, load_ballot, save_standing_delegates,
+    remove_standing_delegates, may_load_standing_delegates, standing_delegator_count, may_load_standing_delegator,
+    save_viewing_key, may_load_viewing_key, save_revoked_permit, save_ranked_votes, load_ranked_votes,
+    save_ranking_id, may_load_ranking_id, save_runoff, may_load_runoff,
+};
+use crate::permit::{self, Permission};
+use crate::viewing_key::{ViewingKey, VIEWING_KEY_SIZE};
+use std::convert::TryInto;
+use cosmwasm_std::{HumanAddr, CanonicalAddr, Binary, ReadonlyStorage};
+use secret_toolkit::crypto::sha_256;
+
+
+
+// Delegation chains are cut at this depth unless the creator chooses otherwise
+pub const DEFAULT_MAX_DELEGATION_DEPTH: u32 = 8;
+// Number of polls listed at once, unless the query asks for fewer
+pub const DEFAULT_POLL_LIMIT: u32 = 10;
+pub const MAX_POLL_LIMIT: u32 = 30;
+// Number of polls read by a single listing, such that filtering by status has a bounded cost
+pub const MAX_POLLS_READ: u64 = 100;
+// Number of standing delegators resolved by a single call closing a tally
+pub const DEFAULT_CLOSE_LIMIT: u32 = 50;
+pub const MAX_CLOSE_LIMIT: u32 = 200;
+// Highest score an option can get in score polls, which bounds the size of the distribution
+pub const MAX_SCORE: u32 = 100;
 
 // Disclaimer: The basic structure is taken from: https://github.com/enigmampc/SecretSimpleVote
 // and is also inspired by https://github.com/baedrik/SCRT-sealed-bid-auction/blob/master/src/contract.rs
@@ -33,19 +54,148 @@ pub fn init<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: InitMsg,
 ) -> InitResult {
-    deps.storage.set(b"poll", &serialize(&msg.poll)?);
+    if msg.electorate.is_some() && msg.mini_public.is_some() {
+        return Err(ContractError::InvalidPoll { reason: String::from("choose either an electorate or a mini public, not both") }.into())
+    }
+
+    // Creator administrates the electorate, which is only restricted if a list is given.
+    // A mini public starts with an empty electorate until its members are drawn.
+    let mut config = Config {
+        admin: deps.api.canonical_address(&env.message.sender)?,
+        restricted_electorate: msg.electorate.is_some() || msg.mini_public.is_some(),
+        max_delegation_depth: msg.max_delegation_depth.unwrap_or(DEFAULT_MAX_DELEGATION_DEPTH),
+        allow_vote_override: msg.allow_vote_override,
+        poll_count: 0,
+        first_open_poll: 0,
+        electorate_size: 0,
+        prng_seed: sha_256(msg.prng_seed.as_slice()).to_vec(),
+        contract_address: env.contract.address.clone(),
+        chain_id: env.block.chain_id.clone(),
+    };
+
+    if let Some(electorate) = msg.electorate {
+        for member in electorate.iter() {
+            let member_raw = deps.api.canonical_address(member)?;
+            if !is_member(&deps.storage, &member_raw) {
+                add_member(&mut deps.storage, &member_raw)?;
+                config.electorate_size += 1;
+            }
+        }
+    }
+    save_config(&mut deps.storage, &config)?;
+
+    if let Some(mini_public) = msg.mini_public {
+        // Candidates given twice are only kept once, such that they can't be drawn twice
+        let mut candidates = Vec::with_capacity(mini_public.candidates.len());
+        for candidate in mini_public.candidates.iter() {
+            let candidate_raw = deps.api.canonical_address(candidate)?;
+            if !candidates.contains(&candidate_raw) {
+                candidates.push(candidate_raw);
+            }
+        }
+        if mini_public.sample_size == 0 || mini_public.sample_size as usize > candidates.len() {
+            return Err(ContractError::InvalidPoll { reason: String::from("sample size must be between 1 and the number of candidates") }.into())
+        }
+        let new_mini_public = MiniPublic {
+            candidates,
+            sample_size: mini_public.sample_size,
+            seed_commitment: mini_public.seed_commitment,
+            draw: None,
+        };
+        save_mini_public(&mut deps.storage, &new_mini_public)?;
+    }
+
+    let poll = Poll {
+        question: msg.poll,
+        options: msg.options,
+        topic: msg.topic,
+        quorum: msg.quorum,
+        threshold: msg.threshold.unwrap_or(Threshold::Majority),
+        method: msg.method.unwrap_or(VotingMethod::SingleChoice),
+    };
+    create_poll(&mut deps.storage, &env, poll, msg.duration, msg.early_results_allowed, msg.reveal_duration)?;
+    Ok(InitResponse::default())
+}
+
+// Stores a new poll along with its empty tally, and returns the id of the poll
+fn create_poll<S: Storage>(storage: &mut S, env: &Env, poll: Poll, duration: u64,
+    early_results_allowed: bool, reveal_duration: Option<u64>) -> StdResult<u64> {
+
+    // A poll needs something to choose between
+    if poll.options.len() < 2 {
+        return Err(ContractError::InvalidPoll { reason: String::from("a poll needs at least two options") }.into())
+    }
 
+    let mut config: Config = load_config(storage)?;
+
+    // A share of the electorate needs a known electorate
+    if let Some(Quorum::Fraction { numerator, denominator }) = poll.quorum {
+        if numerator > denominator {
+            return Err(ContractError::InvalidPoll { reason: String::from("quorum can't exceed the electorate") }.into())
+        }
+        if !config.restricted_electorate {
+            return Err(ContractError::InvalidPoll { reason: String::from("quorum as a share of the electorate needs a restricted electorate") }.into())
+        }
+    }
+    if let Threshold::Fraction { numerator, denominator } = poll.threshold {
+        if numerator == 0 || numerator > denominator {
+            return Err(ContractError::InvalidPoll { reason: String::from("threshold must be a share between 0 and 1") }.into())
+        }
+    }
+    match &poll.method {
+        VotingMethod::Quadratic { credits: 0 } => {
+            return Err(ContractError::InvalidPoll { reason: String::from("voters need credits to vote") }.into())
+        }
+        VotingMethod::Budgeting { costs, .. } if costs.len() != poll.options.len() => {
+            return Err(ContractError::InvalidPoll { reason: String::from("every proposal needs a cost") }.into())
+        }
+        VotingMethod::Score { min, max } if min >= max || *max > MAX_SCORE => {
+            return Err(ContractError::InvalidPoll { reason: format!("scores must range from a minimum to a higher maximum of at most {}", MAX_SCORE) }.into())
+        }
+        _ => {}
+    }
+    // Reveals name a single option
+    if reveal_duration.is_some() && poll.method != VotingMethod::SingleChoice {
+        return Err(ContractError::InvalidPoll { reason: String::from("commit-reveal is only available for single-choice polls") }.into())
+    }
+    // Timestamps must not overflow
+    let end_timestamp = match env.block.time.checked_add(duration) {
+        Some(end_timestamp) => end_timestamp,
+        None => return Err(ContractError::InvalidPoll { reason: String::from("poll would end too far in the future") }.into()),
+    };
+    let reveal_end_timestamp = match reveal_duration {
+        Some(reveal_duration) => match end_timestamp.checked_add(reveal_duration) {
+            Some(reveal_end_timestamp) => Some(reveal_end_timestamp),
+            None => return Err(ContractError::InvalidPoll { reason: String::from("reveal period would end too far in the future") }.into()),
+        },
+        None => None,
+    };
+    let poll_id = config.poll_count;
+    config.poll_count += 1;
+    save_config(storage, &config)?;
+
+    let scores = match poll.method {
+        VotingMethod::Score { max, .. } => vec![vec![0; max as usize + 1]; poll.options.len()],
+        _ => vec![],
+    };
     let new_tally = Tally { 
-        yes: 0, 
-        no: 0, 
-        voters: HashSet::new(),
+        counts: vec![0; poll.options.len()],
+        abstentions: 0,
+        turnout: 0,
+        ranking_count: 0,
+        scores,
         init_timestamp: env.block.time,
-        end_timestamp: msg.duration + env.block.time,
+        end_timestamp,
+        reveal_end_timestamp,
         is_completed: false,
-        early_results_allowed: msg.early_results_allowed};
+        voting_started: false,
+        electorate_size: None,
+        standing_cursor: 0,
+        early_results_allowed};
     
-    deps.storage.set(b"tally", &serialize(&new_tally)?);
-    Ok(InitResponse::default())
+    save_tally(storage, poll_id, &new_tally)?;
+    save_poll(storage, poll_id, &poll)?;
+    Ok(poll_id)
 }
 
 
@@ -62,41 +212,114 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     env: Env,
     msg: HandleMsg,
 ) -> HandleResult {
-    
-    // TODO add handle message to query current ballot
 
-    let mut tally: Tally = deserialize(&deps.storage.get(b"tally").unwrap())?;
+    match msg {
+        HandleMsg::CreatePoll { poll, options, duration, early_results_allowed, topic, reveal_duration, quorum, threshold, method } => {
+            let poll = Poll {
+                question: poll,
+                options,
+                topic,
+                quorum,
+                threshold: threshold.unwrap_or(Threshold::Majority),
+                method: method.unwrap_or(VotingMethod::SingleChoice),
+            };
+            add_poll(deps, &env, poll, duration, early_results_allowed, reveal_duration)
+        }
+        HandleMsg::Vote { poll_id, option } => cast_ballot(deps, env, poll_id, Some(Vote::Option(option)), None),
+        HandleMsg::Rank { poll_id, ranking } => cast_ballot(deps, env, poll_id, Some(Vote::Ranking(ranking)), None),
+        HandleMsg::Approve { poll_id, options } => cast_ballot(deps, env, poll_id, Some(Vote::Approval(options)), None),
+        HandleMsg::Allocate { poll_id, votes } => cast_ballot(deps, env, poll_id, Some(Vote::Quadratic(votes)), None),
+        HandleMsg::Score { poll_id, scores } => cast_ballot(deps, env, poll_id, Some(Vote::Scores(scores)), None),
+        HandleMsg::Abstain { poll_id } => cast_ballot(deps, env, poll_id, Some(Vote::Abstain), None),
+        HandleMsg::CommitVote { poll_id, commitment } => commit_vote(deps, &env, poll_id, commitment),
+        HandleMsg::RevealVote { poll_id, option, salt } => reveal_vote(deps, &env, poll_id, option, salt),
+        HandleMsg::Delegate { poll_id, delegate } => cast_ballot(deps, env, poll_id, None, Some(delegate)),
+        HandleMsg::RevokeDelegation { poll_id } => revoke_delegation(deps, &env, poll_id),
+        HandleMsg::SetStandingDelegate { delegate, topic } => set_standing_delegate(deps, &env, delegate, topic),
+        HandleMsg::ClearStandingDelegate { topic } => clear_standing_delegate(deps, &env, topic),
+        HandleMsg::CloseTally { poll_id, limit } => close_tally(deps, &env, poll_id, limit),
+        HandleMsg::CreateViewingKey { entropy } => create_viewing_key(deps, &env, entropy),
+        HandleMsg::SetViewingKey { key } => set_viewing_key(deps, &env, key),
+        HandleMsg::RevokePermit { permit_name } => revoke_permit(deps, &env, permit_name),
+        HandleMsg::AddElectorate { members } => update_electorate(deps, &env, members, true),
+        HandleMsg::RemoveElectorate { members } => update_electorate(deps, &env, members, false),
+        HandleMsg::DrawMiniPublic { seed } => draw_mini_public(deps, &env, seed),
+    }
+}
+
+// Puts a new poll on the agenda. Anyone who may vote can do so, as well as the admin.
+fn add_poll<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env, poll: Poll,
+    duration: u64, early_results_allowed: bool, reveal_duration: Option<u64>) -> HandleResult {
+
+    let config: Config = load_config(&deps.storage)?;
+    let sender_raw = deps.api.canonical_address(&env.message.sender)?;
+    if sender_raw != config.admin && !is_eligible(deps, &sender_raw)? {
+        return Err(ContractError::NotEligible {}.into())
+    }
+
+    let poll_id = create_poll(&mut deps.storage, env, poll, duration, early_results_allowed, reveal_duration)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::CreatePoll {
+            status: Success,
+            poll_id,
+        })?),
+    })
+}
+
+// Casts a ballot in the given poll, which either votes for an option, abstains or delegates to someone else.
+fn cast_ballot<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: Env, poll_id: u64,
+    vote: Option<Vote>, delegate: Option<HumanAddr>) -> HandleResult {
+
+    let mut tally: Tally = find_tally(&deps.storage, poll_id)?;
+    let config: Config = load_config(&deps.storage)?;
     let voter = &env.message.sender;
     let voter_raw = &deps.api.canonical_address(&voter)?;
     let mut message = String::new();
     let mut vote_value: u64 = 1;
 
-    // First check that msg is valid, ie. it has either vote or delegate, but not both
-    if (msg.vote.is_none() && msg.delegate.is_none()) || (!msg.vote.is_none() && !msg.delegate.is_none()) {
-        
-        // Malformed message TODO better error message
-        return Err(StdError::Unauthorized{backtrace: None})
-    } 
+    // Only members of the electorate can cast a ballot
+    if !is_eligible(deps, voter_raw)? {
+        return Err(ContractError::NotEligible {}.into())
+    }
+
+    if let Some(vote) = &vote {
+        let poll: Poll = load_poll(&deps.storage, poll_id)?;
+        check_vote(&poll, vote)?;
+
+        // Quadratic votes are paid with the credits of the ballot, delegated credits included
+        if let (VotingMethod::Quadratic { credits }, Vote::Quadratic(allocation)) = (&poll.method, vote) {
+            let held = may_load_ballot(&deps.storage, poll_id, voter_raw)?.map_or(1, |ballot| ballot.vote_value);
+            let budget = *credits as u128 * held as u128;
+            let cost = quadratic_cost(allocation);
+            if cost > budget {
+                return Err(ContractError::MalformedBallot { reason: format!("votes cost {} credits, the ballot holds {}", cost, budget) }.into())
+            }
+        }
+
+        // Votes of commit-reveal polls only count once revealed, see commit_vote.
+        // Abstaining tells nothing about one's preference, and is accepted right away.
+        if tally.is_commit_reveal() && vote != &Vote::Abstain {
+            return Err(ContractError::MalformedBallot { reason: String::from("votes of this poll have to be committed") }.into())
+        }
+    }
 
     // Secondly, check whether Tally is still ongoing
     let current_timestamp: u64 = env.block.time;
     
-    if tally.end_timestamp < current_timestamp {
+    if tally.voting_ended(current_timestamp) {
 
-        message.push_str("Tally is over. ");
-        
-        // Change is_completed boolean, such that now the result can be queried
-        // TODO make is such this can be done without having to vote
-        tally.is_completed = true;
-        deps.storage.set(b"tally", &serialize(&tally)?);
+        // The tally is completed with close_tally, whose cost doesn't fall on late voters
+        message.push_str(&format!("{}. ", ContractError::PollClosed {}));
 
-        let mut vote: Option<bool> = None;
+        let mut vote: Option<Vote> = None;
         let mut delegate: Option<HumanAddr> = None;
 
         // Check whether a ballot has been recorded and if so return
         // it with an error message.
-        if tally.voters.contains(&voter_raw.as_slice().to_vec()) {
-            let ballot: Ballot = deserialize(&deps.storage.get(voter_raw.as_slice()).unwrap())?;
+        if let Some(ballot) = may_load_ballot(&deps.storage, poll_id, voter_raw)? {
             if ballot.has_voted {
                 message.push_str("A vote was cast however.");
                 vote = ballot.vote;
@@ -113,6 +336,7 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             log: vec![],
             data: Some(to_binary(&HandleAnswer::Ballot {
                 status: Failure,
+                error_code: Some(ContractError::PollClosed {}.code()),
                 message,
                 vote,
                 delegate
@@ -120,15 +344,15 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
         });
     }
 
-    // Otherwise, Tally is still ongoing
+    // Otherwise, Tally is still ongoing, and the electorate is fixed until it is closed
+    tally.voting_started = true;
 
     // Check if a ballot already exists
-    if tally.voters.contains(&voter_raw.as_slice().to_vec()) {
+    if let Some(mut ballot) = may_load_ballot(&deps.storage, poll_id, voter_raw)? {
 
         print!("Ballot already exists !");
 
         // Check whether it is because of increased vote value, or because a vote was already cast.
-        let mut ballot: Ballot = deserialize(&deps.storage.get(voter_raw.as_slice()).unwrap())?;
 
         if !ballot.has_voted {
 
@@ -137,16 +361,15 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
 
             // Helper function that does the dirty work
             // First, if we have a vote, we vote
-            match msg.vote {
-                Some(v) => {
-                    if v {
-                        tally.yes += vote_value;
-                    } else {
-                        tally.no += vote_value;
-                    }
+            match (&vote, &delegate) {
+                (Some(vote), _) => {
+                    update_votes(&mut deps.storage, poll_id, &mut tally, vote, vote_value, true)?;
                 }
-                None => {
-                    tally = delegate_vote(deps, &env, &msg, tally, &voter, vote_value, &msg.delegate)?
+                (None, Some(delegate)) => {
+                    tally = delegate_vote(deps, &env, poll_id, tally, &voter, &ballot, delegate)?
+                }
+                (None, None) => {
+                    return Err(ContractError::MalformedBallot { reason: String::from("no vote nor delegate") }.into())
                 }
             }
             
@@ -155,27 +378,55 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             // Save new and final ballot
             ballot.has_voted = true;
             ballot.timestamp = env.block.time;
-            ballot.vote = msg.vote;
-            ballot.delegate = msg.delegate;
-            deps.storage.set(voter_raw.as_slice(), &serialize(&ballot)?);
+            ballot.vote = vote.clone();
+            ballot.delegate = delegate;
+            save_ballot(&mut deps.storage, poll_id, voter_raw, &ballot)?;
 
             // Finally store updated tally
-            deps.storage.set(b"tally", &serialize(&tally)?);
+            save_tally(&mut deps.storage, poll_id, &tally)?;
+
+            return Ok(HandleResponse {
+                messages: vec![],
+                log: vec![],
+                data: Some(to_binary(&HandleAnswer::Ballot {
+                    status: Success,
+                    error_code: None,
+                    message,
+                    vote,
+                    delegate: None, // FIXME return delegate
+                })?),
+            });
+
+        } else if let (Some(new_vote), Some(_), true) = (&vote, &ballot.delegate, config.allow_vote_override) {
+
+            // OPTION 2: Voter has delegated, but overrides the delegate by voting directly.
+            // The forwarded votes are taken back from the delegates, and count for the voter's choice.
+            tally = withdraw_delegation(deps, poll_id, tally, &ballot, true)?;
+            update_votes(&mut deps.storage, poll_id, &mut tally, new_vote, ballot.vote_value, true)?;
+
+            message.push_str("Ballot was cast successfully, overriding the delegate!");
+
+            ballot.timestamp = env.block.time;
+            ballot.vote = vote.clone();
+            ballot.delegate = None;
+            save_ballot(&mut deps.storage, poll_id, voter_raw, &ballot)?;
+            save_tally(&mut deps.storage, poll_id, &tally)?;
 
             return Ok(HandleResponse {
                 messages: vec![],
                 log: vec![],
                 data: Some(to_binary(&HandleAnswer::Ballot {
                     status: Success,
+                    error_code: None,
                     message,
-                    vote: msg.vote,
-                    delegate: None, // FIXME return msg.delegate
+                    vote,
+                    delegate: None,
                 })?),
             });
 
         } else {
 
-            // OPTION 2: Voter has already voted.
+            // OPTION 3: Voter has already voted.
             
             // Important: previous feature of changing ballot is not possible in combination with liquid democracy: 
             // It could create a large chain of changes, with unpredictable cost. 
@@ -184,13 +435,14 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
             // In this version, as long as someone hasn't voted the vote value is increased,
             // but once a ballot is finalized, the tally is increased without increasing vote_value.
 
-            message.push_str("Ballot was already cast!");
+            message.push_str(&format!("{}!", ContractError::AlreadyVoted {}));
         
             return Ok(HandleResponse {
                 messages: vec![],
                 log: vec![],
                 data: Some(to_binary(&HandleAnswer::Ballot {
                     status: Failure,
+                    error_code: Some(ContractError::AlreadyVoted {}.code()),
                     message,
                     vote: ballot.vote,
                     delegate: ballot.delegate
@@ -201,302 +453,1368 @@ pub fn handle<S: Storage, A: Api, Q: Querier>(
     // Voter votes for the first time, and doesn't have increased vote value
     } else {
         
-        // OPTION 3: Fresh ballot and single vote
+        // OPTION 4: Fresh ballot and single vote
+
+        let new_ballot = Ballot {
+            has_voted: true,
+            timestamp: env.block.time,
+            vote: vote.clone(),
+            delegate: delegate.clone(), // FIXME add final delegate for future improvements
+            vote_value,
+            commitment: None,
+            incoming_depth: 0,
+        };
 
         // Hard work is done by same helper function
-        match msg.vote {
-            Some(v) => {
-                if v {
-                    tally.yes += vote_value;
-                } else {
-                    tally.no += vote_value;
-                }
+        match (&vote, &delegate) {
+            (Some(vote), _) => {
+                update_votes(&mut deps.storage, poll_id, &mut tally, vote, vote_value, true)?;
             }
-            None => {
-                tally = delegate_vote(deps, &env, &msg, tally, &voter, vote_value, &msg.delegate)?
+            (None, Some(delegate)) => {
+                tally = delegate_vote(deps, &env, poll_id, tally, &voter, &new_ballot, delegate)?
+            }
+            (None, None) => {
+                return Err(ContractError::MalformedBallot { reason: String::from("no vote nor delegate") }.into())
             }
         }
 
         message.push_str("Ballot was cast successfully!");
-        
-        // Add voter to list of voters to prevent double voting
-        tally.voters.insert(voter_raw.as_slice().to_vec());
 
-        // Create and save new ballot
-        let new_ballot = Ballot {
-            has_voted: true,
-            timestamp: env.block.time,
-            vote: msg.vote,
-            delegate: msg.delegate, // FIXME add final delegate for future improvements
-            vote_value: 1
-        };
-        deps.storage.set(voter_raw.as_slice(), &serialize(&new_ballot)?);
+        // Save new ballot, which itself prevents double voting
+        save_ballot(&mut deps.storage, poll_id, voter_raw, &new_ballot)?;
 
         // Finally store updated tally
-        deps.storage.set(b"tally", &serialize(&tally)?);
+        save_tally(&mut deps.storage, poll_id, &tally)?;
 
         return Ok(HandleResponse {
             messages: vec![],
             log: vec![],
             data: Some(to_binary(&HandleAnswer::Ballot {
                 status: Success,
+                error_code: None,
                 message,
-                vote: msg.vote,
+                vote,
                 delegate: None, // FIXME return real delegate. make sure not stored (optimized) delegate, but one chosen by voter
             })?),
         });
     }
 }
 
+// Commits to a vote in a commit-reveal poll. The vote only counts once revealed after voting ended,
+// along with the votes delegated to the voter, which keep adding up to the committed ballot.
+fn commit_vote<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env, poll_id: u64,
+    commitment: Binary) -> HandleResult {
+
+    let mut tally: Tally = find_tally(&deps.storage, poll_id)?;
+    let config: Config = load_config(&deps.storage)?;
+    let voter_raw = deps.api.canonical_address(&env.message.sender)?;
+
+    if !is_eligible(deps, &voter_raw)? {
+        return Err(ContractError::NotEligible {}.into())
+    }
+    if !tally.is_commit_reveal() {
+        return Err(ContractError::MalformedBallot { reason: String::from("poll doesn't use commit-reveal") }.into())
+    }
+    if tally.voting_ended(env.block.time) {
+        return Err(ContractError::PollClosed {}.into())
+    }
+    if commitment.len() != 32 {
+        return Err(ContractError::MalformedBallot { reason: String::from("commitment must be a sha256 hash") }.into())
+    }
+
+    tally.voting_started = true;
+
+    let mut ballot = match may_load_ballot(&deps.storage, poll_id, &voter_raw)? {
+        None => Ballot { has_voted: false, timestamp: env.block.time, vote: None, delegate: None, vote_value: 1, commitment: None, incoming_depth: 0 },
+        // Ballot holding votes delegated to the voter
+        Some(ballot) if !ballot.has_voted => ballot,
+        // Voter overrides its delegate, the forwarded votes are taken back
+        Some(ballot) if ballot.delegate.is_some() && config.allow_vote_override => {
+            tally = withdraw_delegation(deps, poll_id, tally, &ballot, true)?;
+            ballot
+        }
+        Some(_) => return Err(ContractError::AlreadyVoted {}.into()),
+    };
+
+    ballot.has_voted = true;
+    ballot.timestamp = env.block.time;
+    ballot.delegate = None;
+    ballot.commitment = Some(commitment);
+    save_ballot(&mut deps.storage, poll_id, &voter_raw, &ballot)?;
+    save_tally(&mut deps.storage, poll_id, &tally)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Ballot {
+            status: Success,
+            error_code: None,
+            message: String::from("Vote was committed, reveal it once voting ended!"),
+            vote: None,
+            delegate: None,
+        })?),
+    })
+}
+
+// Reveals a committed vote during the reveal window, counting every vote the ballot holds.
+// Votes that aren't revealed in time are lost, along with the votes delegated to them.
+fn reveal_vote<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env, poll_id: u64,
+    option: u32, salt: Binary) -> HandleResult {
+
+    let mut tally: Tally = find_tally(&deps.storage, poll_id)?;
+    if !tally.is_commit_reveal() {
+        return Err(ContractError::MalformedBallot { reason: String::from("poll doesn't use commit-reveal") }.into())
+    }
+    if tally.is_over(env.block.time) {
+        return Err(ContractError::PollClosed {}.into())
+    }
+    if !tally.voting_ended(env.block.time) {
+        return Err(ContractError::NotRevealing {}.into())
+    }
+
+    let voter_raw = deps.api.canonical_address(&env.message.sender)?;
+    let mut ballot = match may_load_ballot(&deps.storage, poll_id, &voter_raw)? {
+        Some(ballot) => ballot,
+        None => return Err(ContractError::NoCommitment {}.into()),
+    };
+    let commitment = match &ballot.commitment {
+        Some(commitment) if ballot.has_voted => commitment.clone(),
+        _ => return Err(ContractError::NoCommitment {}.into()),
+    };
+    if ballot.vote.is_some() {
+        return Err(ContractError::AlreadyVoted {}.into())
+    }
+    if option as usize >= tally.counts.len() {
+        return Err(ContractError::MalformedBallot { reason: format!("option {} does not exist", option) }.into())
+    }
+    if sha_256(&[&option.to_be_bytes()[..], salt.as_slice()].concat()).to_vec() != commitment.as_slice().to_vec() {
+        return Err(ContractError::CommitmentMismatch {}.into())
+    }
+
+    let vote = Vote::Option(option);
+    tally.add_votes(&vote, ballot.vote_value);
+    ballot.vote = Some(vote.clone());
+    save_ballot(&mut deps.storage, poll_id, &voter_raw, &ballot)?;
+    save_tally(&mut deps.storage, poll_id, &tally)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Ballot {
+            status: Success,
+            error_code: None,
+            message: String::from("Vote was revealed and counted!"),
+            vote: Some(vote),
+            delegate: None,
+        })?),
+    })
+}
+
+// Transfers the vote value of the ballot of voter to the end of the chain of delegates starting at delegate.
+// The chain is walked iteratively, and delegations that would close a loop are rejected.
+// Delegates passed along the way keep track of the votes they forward, such that a delegation can be revoked.
+// The depth of the chain counts the delegations leading to the ballot (incoming_depth) as well,
+// such that a chain can't grow past the maximal depth by being extended at its tail.
 // Sideffects: can create up to one new ballot, if final delegate has no ballot yet.
-fn delegate_vote<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env, msg: &HandleMsg, 
-    mut tally: Tally, voter: &HumanAddr, vote_value: u64, delegate: &Option<HumanAddr>) -> StdResult<Tally> {
+fn delegate_vote<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env, poll_id: u64,
+    mut tally: Tally, voter: &HumanAddr, ballot: &Ballot, delegate: &HumanAddr) -> StdResult<Tally> {
 
-    let voter_raw = &deps.api.canonical_address(&voter)?;
-   
-
-    match delegate{
-
-        // No more delegation, end of recursion
-        None => {
-            // The current voter receives the voting power
-            // Check if ballot already exists
-            if tally.voters.contains(&voter_raw.as_slice().to_vec()) {
-                let mut voter_ballot: Ballot = deserialize(&deps.storage.get(voter_raw.as_slice()).unwrap())?;
-
-                // Check if already voted
-                if voter_ballot.has_voted {
-                    match voter_ballot.vote{
-                        Some(v) => {
-                            if v {
-                                tally.yes += vote_value;
-                            } else {
-                                tally.no += vote_value;
-                            }
-                        }
-                        None => {
-                            panic!("unecpected error occurred.")
-                        }
-                    }
-                } else {
-
-                    // Simply increase vote value
-                    voter_ballot.vote_value += vote_value;
-                    deps.storage.set(voter_raw.as_slice(), &serialize(&voter_ballot)?);
-                }
-            } else {
-                // Create ballot.
+    let vote_value = ballot.vote_value;
+    let incoming_depth = ballot.incoming_depth;
+
+    // Addresses met along the chain, starting with the voter itself
+    let mut visited: Vec<CanonicalAddr> = vec![deps.api.canonical_address(&voter)?];
+    // Ballots of the delegates that forward the vote
+    let mut passed: Vec<(CanonicalAddr, Ballot)> = vec![];
+    let mut current = delegate.clone();
+
+    // Length of the chain is bounded, keeping the cost of a delegation predictable
+    let config: Config = load_config(&deps.storage)?;
+
+    loop {
+        let current_raw = deps.api.canonical_address(&current)?;
+
+        // Number of delegations on the longest chain reaching the current delegate
+        let depth = incoming_depth + visited.len() as u32;
+        if depth > config.max_delegation_depth {
+            return Err(ContractError::DelegationTooDeep { max_depth: config.max_delegation_depth }.into())
+        }
+
+        // Coming back to someone on the chain means the vote would never reach a final delegate
+        if visited.contains(&current_raw) {
+            return Err(ContractError::DelegationCycle { delegate: delegate.clone() }.into())
+        }
+
+        // Votes can only be transferred within the electorate
+        if !is_eligible(deps, &current_raw)? {
+            return Err(ContractError::IneligibleDelegate { delegate: current }.into())
+        }
+
+        let mut current_ballot = match may_load_ballot(&deps.storage, poll_id, &current_raw)? {
+            Some(ballot) => ballot,
+            None => {
+                // Final delegate has no ballot yet, create one holding the transferred votes
                 let new_ballot = Ballot {
                     has_voted: false,
                     vote: None,
                     delegate: None,
                     timestamp: env.block.time,
-                    vote_value: 1 + vote_value
+                    vote_value: 1 + vote_value,
+                    commitment: None,
+                    incoming_depth: depth,
                 };
-                deps.storage.set(voter_raw.as_slice(), &serialize(&new_ballot)?);
+                save_ballot(&mut deps.storage, poll_id, &current_raw, &new_ballot)?;
+                forward_vote_value(deps, poll_id, passed, vote_value, incoming_depth)?;
+                return Ok(tally)
+            }
+        };
 
-                // Save reference to tally
-                tally.voters.insert(voter_raw.as_slice().to_vec());
+        if !current_ballot.has_voted {
+            // Final delegate hasn't voted yet, simply increase vote value
+            current_ballot.vote_value += vote_value;
+            current_ballot.incoming_depth = current_ballot.incoming_depth.max(depth);
+            save_ballot(&mut deps.storage, poll_id, &current_raw, &current_ballot)?;
+            forward_vote_value(deps, poll_id, passed, vote_value, incoming_depth)?;
+            return Ok(tally)
+        }
 
-            } 
-            return Ok(tally); 
+        match (current_ballot.vote.clone(), current_ballot.delegate.clone()) {
+            // Credits can only be spent by a delegate who hasn't voted yet
+            (Some(Vote::Quadratic(_)), _) => {
+                return Err(ContractError::DelegateAlreadyVoted { delegate: current }.into())
+            }
+            // Final delegate has voted or abstained, votes count right away.
+            // Its ballot holds them as well, such that they can be taken back on override.
+            (Some(vote), _) => {
+                update_votes(&mut deps.storage, poll_id, &mut tally, &vote, vote_value, true)?;
+                current_ballot.vote_value += vote_value;
+                current_ballot.incoming_depth = current_ballot.incoming_depth.max(depth);
+                save_ballot(&mut deps.storage, poll_id, &current_raw, &current_ballot)?;
+                forward_vote_value(deps, poll_id, passed, vote_value, incoming_depth)?;
+                return Ok(tally)
+            }
+            // Current delegate has delegated as well, keep walking
+            (None, Some(next)) => {
+                visited.push(current_raw.clone());
+                passed.push((current_raw, current_ballot));
+                current = next;
+            }
+            // Final delegate has committed a vote, votes count once it is revealed
+            (None, None) if current_ballot.commitment.is_some() => {
+                current_ballot.vote_value += vote_value;
+                current_ballot.incoming_depth = current_ballot.incoming_depth.max(depth);
+                save_ballot(&mut deps.storage, poll_id, &current_raw, &current_ballot)?;
+                forward_vote_value(deps, poll_id, passed, vote_value, incoming_depth)?;
+                return Ok(tally)
+            }
+            (None, None) => {
+                return Err(ContractError::CorruptBallot {}.into())
+            }
         }
-        Some(delegate) => {
+    }
+}
 
-            let delegate_raw = &deps.api.canonical_address(&delegate)?;
+// Records that the given delegates forward additional votes, through a chain starting after
+// incoming_depth delegations
+fn forward_vote_value<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, poll_id: u64,
+    passed: Vec<(CanonicalAddr, Ballot)>, vote_value: u64, incoming_depth: u32) -> StdResult<()> {
 
-            // Keep recursion going, check whether current delegate has delegated himself
-            if tally.voters.contains(&delegate_raw.as_slice().to_vec()) {
+    for (index, (delegate_raw, mut ballot)) in passed.into_iter().enumerate() {
+        ballot.vote_value += vote_value;
+        ballot.incoming_depth = ballot.incoming_depth.max(incoming_depth + index as u32 + 1);
+        save_ballot(&mut deps.storage, poll_id, &delegate_raw, &ballot)?;
+    }
+    Ok(())
+}
 
-                let delegate_ballot: Ballot = deserialize(&deps.storage.get(&delegate_raw.as_slice()).unwrap())?;
+// Takes back a delegation, as long as the final delegate hasn't voted.
+// The votes forwarded by the voter are removed from every delegate along the chain,
+// and the voter can cast its ballot again, with the votes it had gathered.
+fn revoke_delegation<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env, poll_id: u64) -> HandleResult {
 
-                return delegate_vote(deps, env, msg, tally, &delegate, vote_value, &delegate_ballot.delegate)
-            }
-            else {
-                return delegate_vote(deps, env, msg, tally, &delegate, vote_value, &None)
-            }
-            
-        }  
+    let tally: Tally = find_tally(&deps.storage, poll_id)?;
+    if tally.voting_ended(env.block.time) {
+        return Err(ContractError::PollClosed {}.into())
+    }
+
+    let voter_raw = deps.api.canonical_address(&env.message.sender)?;
+    let mut ballot = match may_load_ballot(&deps.storage, poll_id, &voter_raw)? {
+        Some(ballot) => ballot,
+        None => return Err(ContractError::NotDelegated {}.into()),
+    };
+    if !(ballot.has_voted && ballot.vote.is_none() && ballot.delegate.is_some()) {
+        return Err(ContractError::NotDelegated {}.into())
     }
+
+    let tally = withdraw_delegation(deps, poll_id, tally, &ballot, false)?;
+    save_tally(&mut deps.storage, poll_id, &tally)?;
+
+    // Voter keeps the votes it forwarded, and can use them again
+    ballot.has_voted = false;
+    ballot.delegate = None;
+    ballot.timestamp = env.block.time;
+    save_ballot(&mut deps.storage, poll_id, &voter_raw, &ballot)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: String::from("Delegation was revoked, ballot can be cast again."),
+        })?),
+    })
 }
 
 
-/////////////////////////////////////// Query /////////////////////////////////////
-/// Returns QueryResult
-///
-/// # Arguments
-///
-/// * `deps` - reference to Extern containing all the contract's external dependencies
-/// * `msg` - QueryMsg passed in with the query call
-pub fn query<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, msg: QueryMsg) -> QueryResult {
-    match msg {
-        QueryMsg::GetPoll {} => {
-            let poll: String = deserialize(&deps.storage.get(b"poll").unwrap())?;
-            Ok(to_binary(&poll)?)
+// Removes the votes forwarded by a delegated ballot from every delegate along its chain, the final one included.
+// If the final delegate has already voted, its votes are taken out of the tally, which is only
+// allowed when overriding the delegate. Returns the updated tally, which is not stored.
+fn withdraw_delegation<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, poll_id: u64, mut tally: Tally,
+    ballot: &Ballot, override_delegate: bool) -> StdResult<Tally> {
+
+    let mut current = match &ballot.delegate {
+        Some(delegate) => delegate.clone(),
+        None => return Err(ContractError::NotDelegated {}.into()),
+    };
+
+    // Walk the chain up to the final delegate, which can't be further than the maximal depth
+    let config: Config = load_config(&deps.storage)?;
+    let mut passed: Vec<(CanonicalAddr, Ballot)> = vec![];
+    loop {
+        if passed.len() >= config.max_delegation_depth as usize {
+            return Err(ContractError::DelegationTooDeep { max_depth: config.max_delegation_depth }.into())
         }
-        QueryMsg::GetTally {} => {
-            let tally: Tally = deserialize(&deps.storage.get(b"tally").unwrap())?;
-            
-            // Check whether tally is over and thus can be disclosed
-            if !tally.early_results_allowed && !tally.is_completed {
-                return Err(StdError::Unauthorized{backtrace: None})
-                // FIXME change to more informative answer
+        let current_raw = deps.api.canonical_address(&current)?;
+        let current_ballot: Ballot = load_ballot(&deps.storage, poll_id, &current_raw)?;
+
+        if !current_ballot.has_voted {
+            passed.push((current_raw, current_ballot));
+            break
+        }
+        match (current_ballot.vote.clone(), current_ballot.delegate.clone()) {
+            (Some(vote), _) => {
+                // Credits that were spent can't be taken back
+                if !override_delegate || matches!(vote, Vote::Quadratic(_)) {
+                    return Err(ContractError::DelegateAlreadyVoted { delegate: current }.into())
+                }
+                update_votes(&mut deps.storage, poll_id, &mut tally, &vote, ballot.vote_value, false)?;
+                passed.push((current_raw, current_ballot));
+                break
+            }
+            (None, Some(next)) => {
+                passed.push((current_raw, current_ballot));
+                current = next;
+            }
+            // Final delegate has committed a vote, which isn't counted yet
+            (None, None) if current_ballot.commitment.is_some() => {
+                if !override_delegate {
+                    return Err(ContractError::DelegateAlreadyVoted { delegate: current }.into())
+                }
+                passed.push((current_raw, current_ballot));
+                break
+            }
+            (None, None) => {
+                return Err(ContractError::CorruptBallot {}.into())
             }
-            Ok(to_binary(&tally)?)
         }
- 
-        // Note: Querying vote makes no sense as we do not want to disclose it. 
-        // One can always re-cast a vote in order to get the proof that it was counted. 
-        
     }
-}
 
-pub fn serialize<T: Serialize + Debug>(value: &T) -> StdResult<Vec<u8>> {
-    bincode2::serialize(value)
-        .map_err(|_err| StdError::generic_err(format!("Failed to serialize object: {:?}", value)))
+    for (delegate_raw, mut delegate_ballot) in passed {
+        delegate_ballot.vote_value -= ballot.vote_value;
+        save_ballot(&mut deps.storage, poll_id, &delegate_raw, &delegate_ballot)?;
+    }
+    Ok(tally)
 }
 
-pub fn deserialize<'a, T: Deserialize<'a> + Debug>(data: &'a [u8]) -> StdResult<T> {
-    bincode2::deserialize(data)
-        .map_err(|_err| StdError::generic_err(format!("Failed to serialize object: {:?}", data)))
+// Sets the standing delegate of the sender, who then votes in the sender's place in every poll the sender doesn't vote in.
+// A delegate for a topic only applies to polls on that topic, and takes precedence over the default one.
+// Like within a poll, standing delegations that would close a loop or pass through too many delegates are rejected.
+// Every delegator is visited when a tally is closed, hence only members of a restricted electorate can be delegators.
+fn set_standing_delegate<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env,
+    delegate: HumanAddr, topic: Option<String>) -> HandleResult {
+
+    let config: Config = load_config(&deps.storage)?;
+    if !config.restricted_electorate {
+        return Err(ContractError::OpenElectorate {}.into())
+    }
+    let voter_raw = deps.api.canonical_address(&env.message.sender)?;
+    if !is_eligible(deps, &voter_raw)? {
+        return Err(ContractError::NotEligible {}.into())
+    }
+    let delegate_raw = deps.api.canonical_address(&delegate)?;
+    if !is_eligible(deps, &delegate_raw)? {
+        return Err(ContractError::IneligibleDelegate { delegate }.into())
+    }
+
+    // Walk the standing delegates the ballots of polls on this topic would pass through.
+    // Loops across topics can still appear later on, they are cut when resolving the ballots.
+    let mut visited: Vec<CanonicalAddr> = vec![voter_raw.clone()];
+    let mut next = Some(delegate_raw.clone());
+    while let Some(current) = next {
+        if visited.len() > config.max_delegation_depth as usize {
+            return Err(ContractError::DelegationTooDeep { max_depth: config.max_delegation_depth }.into())
+        }
+        if visited.contains(&current) {
+            return Err(ContractError::DelegationCycle { delegate }.into())
+        }
+        next = standing_delegate(&deps.storage, &current, topic.as_deref())?;
+        visited.push(current);
+    }
+
+    let message = match &topic {
+        Some(topic) => format!("{} is now the standing delegate on {}.", delegate, topic),
+        None => format!("{} is now the standing delegate.", delegate),
+    };
+    let mut delegates = may_load_standing_delegates(&deps.storage, &voter_raw)?.unwrap_or_default();
+    delegates.set(topic, Some(delegate_raw));
+    delegates.updated = env.block.time;
+    save_standing_delegates(&mut deps.storage, &voter_raw, &delegates)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message,
+        })?),
+    })
 }
 
+// Removes the standing delegate of the sender for the given topic, or the default one,
+// for every poll that is still open. Polls that ended no longer follow any of the sender's
+// standing delegates.
+fn clear_standing_delegate<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env,
+    topic: Option<String>) -> HandleResult {
+
+    let voter_raw = deps.api.canonical_address(&env.message.sender)?;
+    let mut delegates = may_load_standing_delegates(&deps.storage, &voter_raw)?.unwrap_or_default();
+    let is_set = match &topic {
+        Some(topic) => delegates.topics.iter().any(|(name, _)| name == topic),
+        None => delegates.default.is_some(),
+    };
+    if !is_set {
+        return Err(ContractError::NotDelegated {}.into())
+    }
 
+    delegates.set(topic, None);
+    delegates.updated = env.block.time;
+    if delegates.is_empty() {
+        remove_standing_delegates(&mut deps.storage, &voter_raw)?;
+    } else {
+        save_standing_delegates(&mut deps.storage, &voter_raw, &delegates)?;
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use cosmwasm_std::testing::{mock_dependencies, mock_env};
-    use cosmwasm_std::{coins, from_binary};
-    //use std::thread;
-    //use std::time;
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: String::from("Standing delegate was removed."),
+        })?),
+    })
+}
 
-    pub const STANDARD_DURATION: u64 = 10000000;
-    
-    #[test]
-    fn proper_initialization() {
-        let mut deps = mock_dependencies(20, &[]);  // canonical length, contract balance
+// Completes the tally, adding the ballots of voters who left the poll to their standing delegate.
+// A voter whose ballot only holds votes delegated to it passes them on as well.
+// At most `limit` standing delegators are resolved, starting after the ones resolved by previous calls,
+// and the tally is only completed once every delegator was. Returns the updated tally, which is not stored.
+fn finalize_tally<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, poll_id: u64,
+    mut tally: Tally, limit: u64) -> StdResult<Tally> {
+
+    let poll: Poll = load_poll(&deps.storage, poll_id)?;
+    let delegator_count = standing_delegator_count(&deps.storage)?;
+    let batch_end = tally.standing_cursor.saturating_add(limit).min(delegator_count);
+    for delegator_id in tally.standing_cursor..batch_end {
+        let delegator_raw = match may_load_standing_delegator(&deps.storage, delegator_id)? {
+            Some(delegator_raw) => delegator_raw,
+            None => continue,
+        };
+        if !is_eligible(deps, &delegator_raw)? {
+            continue
+        }
+        let vote_value = match may_load_ballot(&deps.storage, poll_id, &delegator_raw)? {
+            Some(ballot) if ballot.has_voted => continue,
+            Some(ballot) => ballot.vote_value,
+            None => 1,
+        };
+        // Credits are lost as well, since the final delegate has spent its own already
+        if let Some(vote) = resolve_standing_delegation(deps, poll_id, poll.topic.as_deref(), tally.end_timestamp, &delegator_raw)? {
+            if let Vote::Quadratic(_) = vote {
+                continue
+            }
+            update_votes(&mut deps.storage, poll_id, &mut tally, &vote, vote_value, true)?;
+        }
+    }
+    tally.standing_cursor = batch_end;
+    if batch_end < delegator_count {
+        return Ok(tally)
+    }
 
-        let msg = InitMsg { poll : String::from("Is the sky blue?"), duration: STANDARD_DURATION, early_results_allowed: true};
-        let env = mock_env("creator", &coins(1000, "earth"));
+    if poll.method == VotingMethod::RankedChoice {
+        let mut rankings = Vec::with_capacity(tally.ranking_count as usize);
+        for ranking_id in 0..tally.ranking_count {
+            rankings.push(load_ranked_votes(&deps.storage, poll_id, ranking_id)?);
+        }
+        save_runoff(&mut deps.storage, poll_id, &instant_runoff(tally.counts.len(), &rankings))?;
+    }
 
-        // we can just call .unwrap() to assert this was a success
-        let res = init(&mut deps, env, msg).unwrap();
-        assert_eq!(0, res.messages.len());
+    // Results are decided against the electorate the poll was held in, which may change from now on
+    let config: Config = load_config(&deps.storage)?;
+    if config.restricted_electorate {
+        tally.electorate_size = Some(config.electorate_size);
+    }
 
-        // it worked, let's query the state
-        let res = query(&deps, QueryMsg::GetTally {}).unwrap();
-        let value: Tally = from_binary(&res).unwrap();
+    tally.is_completed = true;
+    Ok(tally)
+}
 
-        assert_eq!(0, value.yes);
-        assert_eq!(0, value.no);
+// Eliminates the options with the fewest votes, passing their votes to the next option ranked on each
+// ballot, until an option has a majority of the votes left. Options tied for the fewest votes are
+// eliminated together, and the runoff ends without a winner when all remaining options are tied.
+fn instant_runoff(option_count: usize, rankings: &[RankedVotes]) -> Runoff {
+    let mut eliminated = vec![false; option_count];
+    let mut rounds = vec![];
+    loop {
+        let mut counts = vec![0u64; option_count];
+        for ranked_votes in rankings {
+            if let Some(&option) = ranked_votes.ranking.iter().find(|&&option| !eliminated[option as usize]) {
+                counts[option as usize] += ranked_votes.votes;
+            }
+        }
+        let remaining: u64 = counts.iter().sum();
+        let active: Vec<usize> = (0..option_count).filter(|&option| !eliminated[option]).collect();
+        let most = active.iter().map(|&option| counts[option]).max().unwrap_or(0);
+        let fewest = active.iter().map(|&option| counts[option]).min().unwrap_or(0);
+        rounds.push(counts);
+
+        if 2 * most > remaining || most == fewest {
+            return Runoff { rounds }
+        }
+        for option in active {
+            if rounds[rounds.len() - 1][option] == fewest {
+                eliminated[option] = true;
+            }
+        }
     }
+}
 
-    #[test]
-    fn vote() {
+// Follows the delegates of a voter who didn't vote, until reaching someone who did.
+// Delegations within the poll come first, otherwise the standing delegate for the topic of the poll is followed.
+// Standing delegates changed after the end of the poll are ignored, as they could follow votes already known.
+// Returns the vote of the final delegate, or None if the ballot is lost along the way.
+fn resolve_standing_delegation<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, poll_id: u64,
+    topic: Option<&str>, end_timestamp: u64, voter_raw: &CanonicalAddr) -> StdResult<Option<Vote>> {
+
+    let config: Config = load_config(&deps.storage)?;
+    let standing_delegate = |voter_raw: &CanonicalAddr| -> StdResult<Option<CanonicalAddr>> {
+        Ok(may_load_standing_delegates(&deps.storage, voter_raw)?
+            .filter(|delegates| delegates.updated <= end_timestamp)
+            .and_then(|delegates| delegates.for_topic(topic).cloned()))
+    };
+    let mut visited: Vec<CanonicalAddr> = vec![voter_raw.clone()];
+    let mut next = standing_delegate(voter_raw)?;
+
+    while let Some(current) = next {
+        // Chains may have grown since they were set, they are cut like the ones within a poll
+        if visited.len() > config.max_delegation_depth as usize || visited.contains(&current) {
+            return Ok(None)
+        }
+        if !is_eligible(deps, &current)? {
+            return Ok(None)
+        }
 
-        let mut deps = mock_dependencies(20, &coins(2, "token")); // amount, denom
+        next = match may_load_ballot(&deps.storage, poll_id, &current)? {
+            Some(Ballot { has_voted: true, vote: Some(vote), .. }) => return Ok(Some(vote)),
+            Some(Ballot { has_voted: true, delegate: Some(delegate), .. }) => Some(deps.api.canonical_address(&delegate)?),
+            // Committed vote that wasn't revealed
+            Some(Ballot { has_voted: true, .. }) => return Ok(None),
+            _ => standing_delegate(&current)?,
+        };
+        visited.push(current);
+    }
+    Ok(None)
+}
 
-        let msg = InitMsg { poll : String::from("Is the sky blue?"), duration: STANDARD_DURATION, early_results_allowed: true};
-        let env = mock_env("creator", &coins(2, "token"));
-        let _res = init(&mut deps, env, msg).unwrap();
+// Standing delegate of the given voter for polls on the given topic, if any
+fn standing_delegate<S: ReadonlyStorage>(storage: &S, voter_raw: &CanonicalAddr, topic: Option<&str>)
+    -> StdResult<Option<CanonicalAddr>> {
 
-        // anyone can vote
-        let env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg{ vote : Some(true), delegate: None};
-        let _res = handle(&mut deps, env, msg).unwrap();
+    Ok(may_load_standing_delegates(storage, voter_raw)?
+        .and_then(|delegates| delegates.for_topic(topic).cloned()))
+}
 
-        // should increase yes tally by 1
-        let res = query(&deps, QueryMsg::GetTally {}).unwrap();
-        let value: Tally = from_binary(&res).unwrap();
-        assert_eq!(1, value.yes);
-        assert_eq!(0, value.no);
+// Marks the tally as completed once its end time has passed, such that results can be queried.
+// Standing delegations are resolved by batches, the tally may take several calls to be closed.
+fn close_tally<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env, poll_id: u64,
+    limit: Option<u32>) -> HandleResult {
 
-        // someone else can vote
-        let env = mock_env("someone else", &coins(3, "token"));
-        let msg = HandleMsg{ vote : Some(false), delegate: None};
-        let _res = handle(&mut deps, env, msg).unwrap();
+    let mut tally: Tally = find_tally(&deps.storage, poll_id)?;
 
-        // should increase yes tally by 1
-        let res = query(&deps, QueryMsg::GetTally {}).unwrap();
-        let value: Tally = from_binary(&res).unwrap();
-        assert_eq!(1, value.yes);
-        assert_eq!(1, value.no);
+    if !tally.is_over(env.block.time) {
+        return Err(ContractError::PollOngoing {}.into())
     }
 
-    #[test]
-    fn poll() {
-        let mut deps = mock_dependencies(20, &coins(2, "token")); // amount, denom
+    let message = if tally.is_completed {
+        String::from("Tally was already closed.")
+    } else {
+        let limit = limit.unwrap_or(DEFAULT_CLOSE_LIMIT).min(MAX_CLOSE_LIMIT);
+        tally = finalize_tally(deps, poll_id, tally, limit as u64)?;
+        save_tally(&mut deps.storage, poll_id, &tally)?;
+        if tally.is_completed {
+            String::from("Tally was closed, results can now be queried.")
+        } else {
+            format!("Resolved the standing delegations of {} delegators, close the tally again to go on.", tally.standing_cursor)
+        }
+    };
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message,
+        })?),
+    })
+}
 
-        // same setup
-        let msg = InitMsg { poll : String::from("Is the sky blue?"), duration: STANDARD_DURATION, early_results_allowed: true};
-        let env = mock_env("creator", &coins(2, "token"));
-        let _res = init(&mut deps, env, msg).unwrap();
+// Creates a viewing key for the sender, replacing any previous one
+fn create_viewing_key<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env, entropy: String) -> HandleResult {
 
-        // anyone can vote
-        let env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg{ vote : Some(true), delegate: None};
-        let _res = handle(&mut deps, env, msg).unwrap();
+    let config: Config = load_config(&deps.storage)?;
+    let key = ViewingKey::new(env, &config.prng_seed, entropy.as_bytes());
+    let owner_raw = deps.api.canonical_address(&env.message.sender)?;
+    save_viewing_key(&mut deps.storage, &owner_raw, &key.to_hashed())?;
 
-        // lets figure out what the poll is
-        let res = query(&deps, QueryMsg::GetPoll {}).unwrap();
-        let value: String = from_binary(&res).unwrap();
-        assert_eq!(value, String::from("Is the sky blue?"));
-    }
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::CreateViewingKey { key })?),
+    })
+}
 
-    #[test]
-    fn vote_only_counts_once() {
-        let mut deps = mock_dependencies(20, &coins(2, "token")); // amount, denom
+// Sets a viewing key chosen by the sender, replacing any previous one
+fn set_viewing_key<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env, key: String) -> HandleResult {
 
-        let msg = InitMsg { poll : String::from("Is the sky blue?"), duration: STANDARD_DURATION, early_results_allowed: true };
-        let env = mock_env("creator", &coins(2, "token"));
-        let _res = init(&mut deps, env, msg).unwrap();
+    let owner_raw = deps.api.canonical_address(&env.message.sender)?;
+    save_viewing_key(&mut deps.storage, &owner_raw, &ViewingKey(key).to_hashed())?;
 
-        // anyone can vote
-        let env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg{ vote : Some(true), delegate: None};
-        let _res = handle(&mut deps, env, msg).unwrap();
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: String::from("Viewing key was set."),
+        })?),
+    })
+}
 
-        // can vote twice, but should only count once though
-        let env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg{ vote : Some(true), delegate: None};
-        let _res = handle(&mut deps, env, msg);
+// Stops accepting the permits the sender signed under the given name
+fn revoke_permit<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env, permit_name: String) -> HandleResult {
 
-        //match _res {
-        //    Err(StdError::Unauthorized { .. }) => {}
-        //        _ => panic!("Must disallow double vote"),
-        //    }
+    let owner_raw = deps.api.canonical_address(&env.message.sender)?;
+    save_revoked_permit(&mut deps.storage, &owner_raw, &permit_name)?;
 
-        // should increase yes tally by 1 only
-        let res = query(&deps, QueryMsg::GetTally {}).unwrap();
-        let value: Tally = from_binary(&res).unwrap();
-        assert_eq!(1, value.yes);
-        assert_eq!(0, value.no);
-    }
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: format!("Permit {} was revoked.", permit_name),
+        })?),
+    })
+}
+
+// Adds or removes members of the electorate. Only the admin can do so, and only before voting starts.
+// Eligibility and the size of the electorate are read when closing a tally, hence the electorate is
+// fixed while a poll with ballots is open, and until every poll that ended is closed. Polls that
+// aren't closed yet are read to find out, at the expense of the admin.
+fn update_electorate<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env,
+    members: Vec<HumanAddr>, add: bool) -> HandleResult {
+
+    let mut config: Config = load_config(&deps.storage)?;
+    if deps.api.canonical_address(&env.message.sender)? != config.admin {
+        return Err(ContractError::NotAdmin {}.into())
+    }
+    for poll_id in config.first_open_poll..config.poll_count {
+        let tally: Tally = find_tally(&deps.storage, poll_id)?;
+        if tally.is_completed {
+            // Closed polls are skipped from now on, as long as no open poll comes before them
+            if poll_id == config.first_open_poll {
+                config.first_open_poll += 1;
+            }
+        } else if tally.voting_started {
+            return Err(ContractError::ElectorateLocked { reason: String::from("a poll with ballots is open") }.into())
+        } else if tally.voting_ended(env.block.time) {
+            return Err(ContractError::ElectorateLocked { reason: String::from("a poll that ended is waiting to be closed") }.into())
+        }
+    }
+    if may_load_mini_public(&deps.storage)?.is_some() {
+        return Err(ContractError::ElectorateLocked { reason: String::from("members of a mini public are drawn") }.into())
+    }
+
+    for member in members.iter() {
+        let member_raw = deps.api.canonical_address(member)?;
+        let was_member = is_member(&deps.storage, &member_raw);
+        if add && !was_member {
+            add_member(&mut deps.storage, &member_raw)?;
+            config.electorate_size += 1;
+        } else if !add && was_member {
+            // Former members no longer take part in closing tallies
+            remove_member(&mut deps.storage, &member_raw);
+            remove_standing_delegates(&mut deps.storage, &member_raw)?;
+            config.electorate_size -= 1;
+        }
+    }
+    let message = if add {
+        format!("Added {} members to the electorate.", members.len())
+    } else {
+        format!("Removed {} members from the electorate.", members.len())
+    };
+
+    // Once members are listed, voting is restricted to them. Removing members from an open
+    // electorate leaves it open, rather than shutting out everyone.
+    if add && !members.is_empty() {
+        config.restricted_electorate = true;
+    }
+    save_config(&mut deps.storage, &config)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message,
+        })?),
+    })
+}
+
+// Draws the members of the mini public and makes them the electorate.
+// The seed has to match the commitment given at init, and is combined with block data.
+// As the admin knows the seed and picks the block it is revealed in, they could try
+// several blocks until the draw suits them: the draw is only as fair as the admin.
+fn draw_mini_public<S: Storage, A: Api, Q: Querier>(deps: &mut Extern<S, A, Q>, env: &Env, seed: Binary) -> HandleResult {
+
+    let config: Config = load_config(&deps.storage)?;
+    if deps.api.canonical_address(&env.message.sender)? != config.admin {
+        return Err(ContractError::NotAdmin {}.into())
+    }
+
+    let mut mini_public = match may_load_mini_public(&deps.storage)? {
+        Some(mini_public) => mini_public,
+        None => return Err(ContractError::NoMiniPublic {}.into()),
+    };
+    if mini_public.draw.is_some() {
+        return Err(ContractError::AlreadyDrawn {}.into())
+    }
+    if sha_256(seed.as_slice()).to_vec() != mini_public.seed_commitment.as_slice().to_vec() {
+        return Err(ContractError::SeedMismatch {}.into())
+    }
+
+    let selected = draw_sample(&mini_public.candidates, mini_public.sample_size, seed.as_slice(), env.block.height, env.block.time);
+
+    for member in selected.iter() {
+        add_member(&mut deps.storage, member)?;
+    }
+    let config = Config { electorate_size: selected.len() as u64, ..config };
+    save_config(&mut deps.storage, &config)?;
+
+    mini_public.draw = Some(Draw {
+        seed,
+        block_height: env.block.height,
+        block_time: env.block.time,
+        selected,
+    });
+    save_mini_public(&mut deps.storage, &mini_public)?;
+
+    Ok(HandleResponse {
+        messages: vec![],
+        log: vec![],
+        data: Some(to_binary(&HandleAnswer::Status {
+            status: Success,
+            message: format!("Drew {} members of the mini public.", mini_public.sample_size),
+        })?),
+    })
+}
+
+/// Returns the randomly selected subset of `candidates`
+///
+/// The draw is deterministic, such that anyone can reproduce it from the revealed seed and block data:
+/// the randomness is `sha256(seed || height || time)` (big endian), and for each position `i` of the
+/// sample, `sha256(randomness || i)` picks one of the remaining candidates (partial Fisher-Yates shuffle).
+///
+/// # Arguments
+///
+/// * `candidates` - pool to draw from, in the order given at init
+/// * `sample_size` - number of candidates to select
+/// * `seed` - seed revealed by the admin
+/// * `block_height` - height of the block of the draw
+/// * `block_time` - time of the block of the draw
+pub fn draw_sample<T: Clone>(candidates: &[T], sample_size: u32, seed: &[u8], block_height: u64, block_time: u64) -> Vec<T> {
+    let randomness = sha_256(&[seed, &block_height.to_be_bytes(), &block_time.to_be_bytes()].concat());
+
+    let mut pool = candidates.to_vec();
+    let sample_size = (sample_size as usize).min(pool.len());
+    for i in 0..sample_size {
+        let hash = sha_256(&[&randomness[..], &(i as u32).to_be_bytes()].concat());
+        let random = u64::from_be_bytes(hash[..8].try_into().unwrap());
+        let j = i + (random % (pool.len() - i) as u64) as usize;
+        pool.swap(i, j);
+    }
+    pool.truncate(sample_size);
+    pool
+}
+
+// Loads the tally of the given poll, which has to exist
+fn find_tally<S: ReadonlyStorage>(storage: &S, poll_id: u64) -> StdResult<Tally> {
+    match may_load_tally(storage, poll_id)? {
+        Some(tally) => Ok(tally),
+        None => Err(ContractError::UnknownPoll { poll_id }.into()),
+    }
+}
+
+// Describes the given poll, whose tally was loaded already
+fn poll_response<S: ReadonlyStorage>(storage: &S, poll_id: u64, tally: Tally) -> StdResult<PollResponse> {
+    let poll: Poll = load_poll(storage, poll_id)?;
+    Ok(PollResponse {
+        poll_id,
+        poll: poll.question,
+        options: poll.options,
+        topic: poll.topic,
+        init_timestamp: tally.init_timestamp,
+        end_timestamp: tally.end_timestamp,
+        reveal_end_timestamp: tally.reveal_end_timestamp,
+        early_results_allowed: tally.early_results_allowed,
+        is_completed: tally.is_completed,
+        quorum: poll.quorum,
+        threshold: poll.threshold,
+        method: poll.method,
+    })
+}
+
+// Decides whether the poll passed: once the quorum is reached, the leading option
+// passes if it reaches the threshold of the votes cast for an option, abstentions aside.
+// Ranked-choice polls are decided by the last round of their runoff, among the votes left.
+// In approval polls, the threshold is the share of voters approving the leading option.
+// In quadratic polls, it is the share of all votes given.
+// Ties between leading options are rejected.
+// Budgeting polls pass once they fund a proposal, regardless of the threshold.
+// Score polls elect the option with the best average score, regardless of the threshold.
+fn poll_result<S: ReadonlyStorage>(storage: &S, poll_id: u64) -> StdResult<ResultResponse> {
+    let tally: Tally = find_tally(storage, poll_id)?;
+    if !tally.is_completed {
+        return Err(ContractError::PollOngoing {}.into())
+    }
+    let poll: Poll = load_poll(storage, poll_id)?;
+    let electorate_size = tally.electorate_size;
+
+    let turnout = tally.turnout;
+    let rounds = may_load_runoff(storage, poll_id)?.map_or_else(Vec::new, |runoff| runoff.rounds);
+    let decisive = rounds.last().unwrap_or(&tally.counts);
+    let decisive_total = match &poll.method {
+        VotingMethod::Approval => turnout - tally.abstentions,
+        _ => decisive.iter().sum(),
+    };
+    let max = decisive.iter().copied().max().unwrap_or(0);
+    let mut leaders = (0..decisive.len()).filter(|&option| decisive[option] == max);
+    let leader = match (leaders.next(), leaders.next()) {
+        (Some(option), None) => Some(option),
+        _ => None,
+    };
+
+    let quorum_reached = match poll.quorum {
+        Some(quorum) => quorum.is_reached(turnout, electorate_size.unwrap_or(0)),
+        None => true,
+    };
+
+    // Budgeting polls fund several proposals rather than electing a single option
+    let funding = match &poll.method {
+        VotingMethod::Budgeting { budget, costs } if quorum_reached => Some(fund_proposals(&tally.counts, costs, *budget)),
+        VotingMethod::Budgeting { budget, .. } => Some(Funding { funded: vec![], leftover_budget: *budget }),
+        _ => None,
+    };
+    // Every score ballot scores every option, so the best total is the best average
+    let winner = leader.filter(|&option| quorum_reached && match &poll.method {
+        VotingMethod::Budgeting { .. } => false,
+        VotingMethod::Score { .. } => true,
+        _ => poll.threshold.is_reached(decisive[option], decisive_total),
+    });
+    let scores = match &poll.method {
+        VotingMethod::Score { min, .. } => Some(score_results(&tally, *min)),
+        _ => None,
+    };
+    let outcome = match (&funding, winner) {
+        _ if !quorum_reached => Outcome::QuorumNotReached,
+        (Some(funding), _) if !funding.funded.is_empty() => Outcome::Passed,
+        (None, Some(_)) => Outcome::Passed,
+        _ => Outcome::Rejected,
+    };
+
+    let winner = winner.map(|option| option as u32);
+    let options = poll.options.into_iter()
+        .zip(tally.counts.iter().copied())
+        .map(|(label, count)| OptionTally { label, count })
+        .collect();
+
+    Ok(ResultResponse { outcome, winner, rounds, funding, scores, turnout, electorate_size, options })
+}
+
+// Average score of each option among the votes that scored it, and how often each score was given
+fn score_results(tally: &Tally, min: u32) -> Vec<OptionScores> {
+    let voters = tally.turnout - tally.abstentions;
+    tally.counts.iter().zip(tally.scores.iter())
+        .map(|(&total, distribution)| OptionScores {
+            average: if voters == 0 { Decimal::zero() } else { Decimal::from_ratio(total as u128, voters as u128) },
+            distribution: distribution[min as usize..].to_vec(),
+        })
+        .collect()
+}
+
+// Funds proposals by decreasing approvals, skipping the ones that no longer fit in the budget.
+// Proposals nobody approves of aren't funded, ties go to the proposal listed first.
+fn fund_proposals(approvals: &[u64], costs: &[u64], budget: u64) -> Funding {
+    let mut proposals: Vec<usize> = (0..approvals.len()).filter(|&proposal| approvals[proposal] > 0).collect();
+    proposals.sort_by(|&a, &b| approvals[b].cmp(&approvals[a]));
+
+    let mut funded = vec![];
+    let mut leftover_budget = budget;
+    for proposal in proposals {
+        if costs[proposal] <= leftover_budget {
+            leftover_budget -= costs[proposal];
+            funded.push(proposal as u32);
+        }
+    }
+    Funding { funded, leftover_budget }
+}
+
+// Standing delegates of the given voter, by default and for each topic
+fn standing_delegate_response<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, voter_raw: &CanonicalAddr)
+    -> StdResult<StandingDelegateResponse> {
+
+    let delegates = may_load_standing_delegates(&deps.storage, voter_raw)?.unwrap_or_default();
+    let delegate = match delegates.default {
+        Some(delegate_raw) => Some(deps.api.human_address(&delegate_raw)?),
+        None => None,
+    };
+    let mut topics = Vec::with_capacity(delegates.topics.len());
+    for (topic, delegate_raw) in delegates.topics {
+        topics.push(TopicDelegate { topic, delegate: deps.api.human_address(&delegate_raw)? });
+    }
+    Ok(StandingDelegateResponse { delegate, topics })
+}
+
+// Checks the viewing key of the given address. Without a stored key, a dummy comparison
+// still takes place, such that the timing doesn't tell whether the address has a key.
+fn check_viewing_key<S: ReadonlyStorage>(storage: &S, address_raw: &CanonicalAddr, key: String) -> StdResult<()> {
+    let hashed = may_load_viewing_key(storage, address_raw)?;
+    let is_valid = ViewingKey(key).check_viewing_key(&hashed.unwrap_or([0u8; VIEWING_KEY_SIZE]));
+    if hashed.is_none() || !is_valid {
+        return Err(ContractError::WrongViewingKey {}.into())
+    }
+    Ok(())
+}
+
+// Checks that a vote fits the voting method of the poll
+fn check_vote(poll: &Poll, vote: &Vote) -> StdResult<()> {
+    let check_option = |option: u32| -> StdResult<()> {
+        if option as usize >= poll.options.len() {
+            return Err(ContractError::MalformedBallot { reason: format!("option {} does not exist", option) }.into())
+        }
+        Ok(())
+    };
+
+    // Lists of options can't be empty, nor name an option twice
+    let check_options = |options: &[u32]| -> StdResult<()> {
+        if options.is_empty() {
+            return Err(ContractError::MalformedBallot { reason: String::from("a ballot needs at least one option") }.into())
+        }
+        for (index, &option) in options.iter().enumerate() {
+            check_option(option)?;
+            if options[..index].contains(&option) {
+                return Err(ContractError::MalformedBallot { reason: format!("option {} is listed twice", option) }.into())
+            }
+        }
+        Ok(())
+    };
+
+    match (&poll.method, vote) {
+        (_, Vote::Abstain) => Ok(()),
+        (VotingMethod::SingleChoice, Vote::Option(option)) => check_option(*option),
+        (VotingMethod::RankedChoice, Vote::Ranking(ranking)) => check_options(ranking),
+        (VotingMethod::Approval, Vote::Approval(options)) => check_options(options),
+        (VotingMethod::Budgeting { .. }, Vote::Approval(options)) => check_options(options),
+        (VotingMethod::Quadratic { .. }, Vote::Quadratic(allocation)) => {
+            check_options(&allocation.iter().map(|option_votes| option_votes.option).collect::<Vec<u32>>())
+        }
+        (VotingMethod::Score { min, max }, Vote::Scores(scores)) => {
+            if scores.len() != poll.options.len() {
+                return Err(ContractError::MalformedBallot { reason: String::from("every option needs a score") }.into())
+            }
+            match scores.iter().find(|&score| score < min || score > max) {
+                Some(score) => Err(ContractError::MalformedBallot { reason: format!("score {} is out of range", score) }.into()),
+                None => Ok(()),
+            }
+        }
+        _ => Err(ContractError::MalformedBallot { reason: String::from("ballot doesn't fit the voting method of the poll") }.into()),
+    }
+}
+
+// Number of credits a quadratic vote costs, n votes for an option cost n²
+fn quadratic_cost(allocation: &[OptionVotes]) -> u128 {
+    allocation.iter()
+        .map(|option_votes| option_votes.votes as u128 * option_votes.votes as u128)
+        .sum()
+}
+
+// Counts votes for the choice of a ballot, or takes them back. In ranked-choice polls, the votes
+// are also added to the votes of their ranking, on which the runoff is run.
+fn update_votes<S: Storage>(storage: &mut S, poll_id: u64, tally: &mut Tally, vote: &Vote, votes: u64, add: bool) -> StdResult<()> {
+    if let Vote::Ranking(ranking) = vote {
+        let (ranking_id, mut ranked_votes) = match may_load_ranking_id(storage, poll_id, ranking)? {
+            Some(ranking_id) => (ranking_id, load_ranked_votes(storage, poll_id, ranking_id)?),
+            None => {
+                let ranking_id = tally.ranking_count;
+                tally.ranking_count += 1;
+                save_ranking_id(storage, poll_id, ranking, ranking_id)?;
+                (ranking_id, RankedVotes { ranking: ranking.clone(), votes: 0 })
+            }
+        };
+        if add {
+            ranked_votes.votes += votes;
+        } else {
+            ranked_votes.votes -= votes;
+        }
+        save_ranked_votes(storage, poll_id, ranking_id, &ranked_votes)?;
+    }
+
+    if add {
+        tally.add_votes(vote, votes);
+    } else {
+        tally.remove_votes(vote, votes);
+    }
+    Ok(())
+}
+
+// Describes the ballot of an authenticated voter in the given poll
+fn my_ballot<S: ReadonlyStorage>(storage: &S, poll_id: u64, voter_raw: &CanonicalAddr) -> StdResult<MyBallotResponse> {
+    find_tally(storage, poll_id)?;
+
+    // Without a ballot, one's own vote is still to be cast
+    Ok(match may_load_ballot(storage, poll_id, voter_raw)? {
+        Some(ballot) => MyBallotResponse {
+            has_voted: ballot.has_voted,
+            vote: ballot.vote,
+            delegate: ballot.delegate,
+            vote_value: ballot.vote_value,
+        },
+        None => MyBallotResponse { has_voted: false, vote: None, delegate: None, vote_value: 1 },
+    })
+}
+
+// Whether the given address may vote, ie. the electorate is open to all or it is a listed member
+fn is_eligible<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, voter_raw: &CanonicalAddr) -> StdResult<bool> {
+    let config: Config = load_config(&deps.storage)?;
+    if !config.restricted_electorate {
+        return Ok(true)
+    }
+    Ok(is_member(&deps.storage, voter_raw))
+}
+
+
+/////////////////////////////////////// Query /////////////////////////////////////
+/// Returns QueryResult
+///
+/// # Arguments
+///
+/// * `deps` - reference to Extern containing all the contract's external dependencies
+/// * `msg` - QueryMsg passed in with the query call
+pub fn query<S: Storage, A: Api, Q: Querier>(deps: &Extern<S, A, Q>, msg: QueryMsg) -> QueryResult {
+    match msg {
+        QueryMsg::GetPoll { poll_id } => {
+            let tally: Tally = find_tally(&deps.storage, poll_id)?;
+            Ok(to_binary(&poll_response(&deps.storage, poll_id, tally)?)?)
+        }
+        QueryMsg::ListPolls { status, start_after, limit } => {
+            let config: Config = load_config(&deps.storage)?;
+            let limit = limit.unwrap_or(DEFAULT_POLL_LIMIT).min(MAX_POLL_LIMIT) as usize;
+
+            // Whether a poll is open relies on its tally being closed, like the results do
+            let mut polls = vec![];
+            let mut poll_id = start_after.map_or(0, |id| id.saturating_add(1));
+            let read_end = poll_id.saturating_add(MAX_POLLS_READ).min(config.poll_count);
+            while poll_id < read_end && polls.len() < limit {
+                let tally: Tally = find_tally(&deps.storage, poll_id)?;
+                let selected = match status {
+                    Some(PollStatus::Open) => !tally.is_completed,
+                    Some(PollStatus::Closed) => tally.is_completed,
+                    None => true,
+                };
+                if selected {
+                    polls.push(poll_response(&deps.storage, poll_id, tally)?);
+                }
+                poll_id += 1;
+            }
+            // Listing goes on after the last poll read, as long as some are left
+            let next_start_after = if poll_id < config.poll_count { poll_id.checked_sub(1) } else { None };
+            Ok(to_binary(&ListPollsResponse { polls, next_start_after })?)
+        }
+        QueryMsg::GetTally { poll_id } => {
+            let tally: Tally = find_tally(&deps.storage, poll_id)?;
+            
+            // Check whether tally is over and thus can be disclosed.
+            // Queries of CosmWasm 0.10 get no Env, so completion can't follow from the end time:
+            // we rely on the flag set by close_tally, and return the end time such that clients
+            // can tell whether voting ended themselves.
+            if !tally.early_results_allowed && !tally.is_completed {
+                return Err(ContractError::ResultsSealed {}.into())
+            }
+
+            // Pair each counter with the label of its option
+            let poll: Poll = load_poll(&deps.storage, poll_id)?;
+            let options = poll.options.into_iter()
+                .zip(tally.counts)
+                .map(|(label, count)| OptionTally { label, count })
+                .collect();
+
+            Ok(to_binary(&TallyResponse { options, abstentions: tally.abstentions, is_completed: tally.is_completed, end_timestamp: tally.end_timestamp })?)
+        }
+        QueryMsg::GetResult { poll_id } => Ok(to_binary(&poll_result(&deps.storage, poll_id)?)?),
+        QueryMsg::GetConfig {} => {
+            let config: Config = load_config(&deps.storage)?;
+            Ok(to_binary(&ConfigResponse {
+                admin: deps.api.human_address(&config.admin)?,
+                restricted_electorate: config.restricted_electorate,
+                max_delegation_depth: config.max_delegation_depth,
+                allow_vote_override: config.allow_vote_override,
+                poll_count: config.poll_count,
+                electorate_size: config.electorate_size,
+            })?)
+        }
+        QueryMsg::GetStandingDelegate { address, key } => {
+            let address_raw = deps.api.canonical_address(&address)?;
+            check_viewing_key(&deps.storage, &address_raw, key)?;
+            Ok(to_binary(&standing_delegate_response(deps, &address_raw)?)?)
+        }
+        QueryMsg::GetMyBallot { poll_id, address, key } => {
+            let address_raw = deps.api.canonical_address(&address)?;
+            check_viewing_key(&deps.storage, &address_raw, key)?;
+            Ok(to_binary(&my_ballot(&deps.storage, poll_id, &address_raw)?)?)
+        }
+        QueryMsg::WithPermit { permit, query } => {
+            let config: Config = load_config(&deps.storage)?;
+            match query {
+                QueryWithPermit::GetMyBallot { poll_id } => {
+                    let address_raw = permit::validate(&deps.storage, &permit, &config.contract_address, &config.chain_id, Permission::Ballot)?;
+                    Ok(to_binary(&my_ballot(&deps.storage, poll_id, &address_raw)?)?)
+                }
+                QueryWithPermit::GetStandingDelegate {} => {
+                    let address_raw = permit::validate(&deps.storage, &permit, &config.contract_address, &config.chain_id, Permission::Ballot)?;
+                    Ok(to_binary(&standing_delegate_response(deps, &address_raw)?)?)
+                }
+            }
+        }
+        QueryMsg::GetMiniPublic {} => {
+            let mini_public = match may_load_mini_public(&deps.storage)? {
+                Some(mini_public) => mini_public,
+                None => return Err(ContractError::NoMiniPublic {}.into()),
+            };
+
+            let mut candidates = Vec::with_capacity(mini_public.candidates.len());
+            for candidate in mini_public.candidates.iter() {
+                candidates.push(deps.api.human_address(candidate)?);
+            }
+            let mut response = MiniPublicResponse {
+                candidates,
+                sample_size: mini_public.sample_size,
+                seed_commitment: mini_public.seed_commitment,
+                seed: None,
+                block_height: None,
+                block_time: None,
+                selected: None,
+            };
+
+            // Everything needed to reproduce the draw is disclosed once it took place
+            if let Some(draw) = mini_public.draw {
+                let mut selected = Vec::with_capacity(draw.selected.len());
+                for member in draw.selected.iter() {
+                    selected.push(deps.api.human_address(member)?);
+                }
+                response.seed = Some(draw.seed);
+                response.block_height = Some(draw.block_height);
+                response.block_time = Some(draw.block_time);
+                response.selected = Some(selected);
+            }
+            Ok(to_binary(&response)?)
+        }
+ 
+        // Note: Votes are only disclosed to their voter, through a viewing key.
+        // One can also re-cast a vote in order to get the proof that it was counted.
+        
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::msg::MiniPublicInit;
+    use std::fmt::Debug;
+    use cosmwasm_std::testing::{mock_dependencies, mock_env, MockStorage};
+    use std::cell::Cell;
+    use cosmwasm_std::{coins, from_binary, from_slice, StdError};
+    //use std::thread;
+    //use std::time;
+
+    pub const STANDARD_DURATION: u64 = 10000000;
+
+    fn yes_no() -> Vec<String> {
+        vec![String::from("yes"), String::from("no")]
+    }
+
+    // Poll 0 on yes or no, open to anyone, with every setting left to its default
+    fn default_init_msg() -> InitMsg {
+        InitMsg { poll: String::from("Should we buy new benches?"), options: yes_no(), duration: STANDARD_DURATION, early_results_allowed: true, electorate: None, mini_public: None, max_delegation_depth: None, allow_vote_override: false, topic: None, prng_seed: Binary(b"seed".to_vec()), reveal_duration: None, quorum: None, threshold: None, method: None }
+    }
+
+    // Electorate of the tests on standing delegates, which need a restricted electorate
+    fn neighbors() -> Option<Vec<HumanAddr>> {
+        Some(["Anna", "Bert", "Carl", "Dora", "Emil", "John"].iter().map(|name| HumanAddr(name.to_string())).collect())
+    }
+
+    fn assert_contract_error<T: Debug>(res: StdResult<T>, expected: ContractError) {
+        match (res, StdError::from(expected)) {
+            (Err(StdError::GenericErr { msg, .. }), StdError::GenericErr { msg: expected_msg, .. }) => {
+                assert_eq!(msg, expected_msg)
+            }
+            (res, _) => panic!("Unexpected result: {:?}", res),
+        }
+    }
+    
+    #[test]
+    fn proper_initialization() {
+        let mut deps = mock_dependencies(20, &[]);  // canonical length, contract balance
+
+        let msg = InitMsg { poll: String::from("Is the sky blue?"), ..default_init_msg() };
+        let env = mock_env("creator", &coins(1000, "earth"));
+
+        // we can just call .unwrap() to assert this was a success
+        let res = init(&mut deps, env, msg).unwrap();
+        assert_eq!(0, res.messages.len());
+
+        // it worked, let's query the state
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+
+        assert_eq!(0, value.options[0].count);
+        assert_eq!(0, value.options[1].count);
+    }
+
+    #[test]
+    fn vote() {
+
+        let mut deps = mock_dependencies(20, &coins(2, "token")); // amount, denom
+
+        let msg = InitMsg { poll: String::from("Is the sky blue?"), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // anyone can vote
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // should increase yes tally by 1
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert_eq!(0, value.options[1].count);
+
+        // someone else can vote
+        let env = mock_env("someone else", &coins(3, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // should increase yes tally by 1
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert_eq!(1, value.options[1].count);
+    }
+
+    #[test]
+    fn poll() {
+        let mut deps = mock_dependencies(20, &coins(2, "token")); // amount, denom
+
+        // same setup
+        let msg = InitMsg { poll: String::from("Is the sky blue?"), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // anyone can vote
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // lets figure out what the poll is
+        let res = query(&deps, QueryMsg::GetPoll { poll_id: 0 }).unwrap();
+        let value: PollResponse = from_binary(&res).unwrap();
+        assert_eq!(value.poll, String::from("Is the sky blue?"));
+        assert_eq!(value.options, yes_no());
+    }
+
+    #[test]
+    fn vote_only_counts_once() {
+        let mut deps = mock_dependencies(20, &coins(2, "token")); // amount, denom
+
+        let msg = InitMsg { poll: String::from("Is the sky blue?"), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // anyone can vote
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // can vote twice, but should only count once though
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let res = handle(&mut deps, env, msg).unwrap();
+        let answer: HandleAnswer = from_binary(&res.data.unwrap()).unwrap();
+        match answer {
+            HandleAnswer::Ballot { status: Failure, error_code, .. } => assert_eq!(Some(4), error_code),
+            _ => panic!("Must disallow double vote"),
+        }
+
+        // should increase yes tally by 1 only
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert_eq!(0, value.options[1].count);
+    }
 
     #[test]
     fn cant_change_mind() {
         let mut deps = mock_dependencies(20, &coins(2, "token")); // amount, denom
 
-        let msg = InitMsg { poll : String::from("Is the sky blue?"), duration: STANDARD_DURATION, early_results_allowed: true };
+        let msg = InitMsg { poll: String::from("Is the sky blue?"), ..default_init_msg() };
         let env = mock_env("creator", &coins(2, "token"));
         let _res = init(&mut deps, env, msg).unwrap();
 
         // anyone can vote
         let env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg{ vote : Some(true), delegate: None};
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
         let _res = handle(&mut deps, env, msg).unwrap();
 
         // cant change mind
         let env = mock_env("anyone", &coins(2, "token"));
-        let msg = HandleMsg{ vote : Some(false), delegate: None};
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
         let _res = handle(&mut deps, env, msg);
 
         // should increase true tally by 1 only
-        let res = query(&deps, QueryMsg::GetTally {}).unwrap();
-        let value: Tally = from_binary(&res).unwrap();
-        assert_eq!(1, value.yes);
-        assert_eq!(0, value.no);
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert_eq!(0, value.options[1].count);
     }
 
 
@@ -504,26 +1822,26 @@ mod tests {
     fn simple_delegation() {
         let mut deps = mock_dependencies(20, &coins(2, "token"));
 
-        let msg = InitMsg { poll : String::from("Is the sky blue?"), duration: STANDARD_DURATION, early_results_allowed: true };
+        let msg = InitMsg { poll: String::from("Is the sky blue?"), ..default_init_msg() };
         let env = mock_env("creator", &coins(2, "token"));
         let _res = init(&mut deps, env, msg).unwrap();
 
         // Max can vote and delegate to franz
         let env = mock_env("Max", &coins(2, "token"));
         let delegate : HumanAddr = HumanAddr("John".to_string());
-        let msg = HandleMsg{ vote : None, delegate: Some(delegate)};
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: delegate };
         let _res = handle(&mut deps, env, msg).unwrap();
 
         // John can vote and his vote is thus worth 2
         let env = mock_env("John", &coins(2, "token"));
-        let msg = HandleMsg{ vote : Some(true), delegate: None};
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
         let _res = handle(&mut deps, env, msg);
 
         // Should increase tally by 2
-        let res = query(&deps, QueryMsg::GetTally {}).unwrap();
-        let value: Tally = from_binary(&res).unwrap();
-        assert_eq!(2, value.yes);
-        assert_eq!(0, value.no);
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.options[0].count);
+        assert_eq!(0, value.options[1].count);
     }
 
     #[test]
@@ -531,65 +1849,2019 @@ mod tests {
 
         let mut deps = mock_dependencies(20, &coins(2, "token"));
 
-        let msg = InitMsg { poll : String::from("Should we buy new benches?"), duration: STANDARD_DURATION, early_results_allowed: true };
+        let msg = default_init_msg();
         let env = mock_env("creator", &coins(2, "token"));
         let _res = init(&mut deps, env, msg).unwrap();
 
         // John can vote
         let env = mock_env("John", &coins(42, "token"));
-        let msg = HandleMsg{ vote : Some(true), delegate: None};
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
         let _res = handle(&mut deps, env, msg);
   
         // Max can vote, he delegates to John, and thus John's vote should count twice
         let env = mock_env("Max", &coins(35, "token"));
         let delegate : HumanAddr = HumanAddr("John".to_string());
-        let msg = HandleMsg{ vote : None, delegate: Some(delegate)};
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: delegate };
         let _res = handle(&mut deps, env, msg).unwrap();
 
         // Should increase tally by 2
-        let res = query(&deps, QueryMsg::GetTally {}).unwrap();
-        let value: Tally = from_binary(&res).unwrap();
-        assert_eq!(2, value.yes);
-        assert_eq!(0, value.no);
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.options[0].count);
+        assert_eq!(0, value.options[1].count);
 
     }
 
     #[test]
-    fn bad_vote_throws_error() {
-        let mut deps = mock_dependencies(20, &coins(2, "token")); // amount, denom
+    fn delegation_chain() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
 
-        let msg = InitMsg { poll : String::from("Is the sky blue?"), duration: STANDARD_DURATION, early_results_allowed: true };
+        let msg = default_init_msg();
         let env = mock_env("creator", &coins(2, "token"));
         let _res = init(&mut deps, env, msg).unwrap();
 
-        // Max can't vote and delegate to John at the same time
-        let env = mock_env("Max", &coins(2, "token"));
-        let delegate : HumanAddr = HumanAddr("John".to_string());
-        let msg = HandleMsg{ vote : Some(true), delegate: Some(delegate)};
-        let res = handle(&mut deps, env, msg);
+        // Anna delegates to Bert, who delegates to Carl
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Bert".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
 
-        match res {
-            Err(StdError::Unauthorized { .. }) => {}
-                _ => panic!("Must disallow double vote"),
-            }
-        
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Carl".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Dora delegates to Anna, and her vote travels the whole chain
+        let env = mock_env("Dora", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
 
-        // Tally should not have moved
-        // should increase tally by 2
-        let res = query(&deps, QueryMsg::GetTally {}).unwrap();
-        let value: Tally = from_binary(&res).unwrap();
-        assert_eq!(0, value.yes);
-        assert_eq!(0, value.no);
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(0, value.options[0].count);
+        assert_eq!(4, value.options[1].count);
     }
 
+    #[test]
+    fn self_delegation_throws_error() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = default_init_msg();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::DelegationCycle { delegate: HumanAddr("Anna".to_string()) });
+    }
 
     #[test]
-    fn no_more_voting_after_end() {
-        // TODO
+    fn two_cycle_throws_error() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = default_init_msg();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Bert".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Bert can't delegate back to Anna
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::DelegationCycle { delegate: HumanAddr("Anna".to_string()) });
+
+        // But he can still vote, with Anna's vote
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.options[0].count);
     }
 
     #[test]
-    fn secret_tally_is_revealed_after_end() {
-        // TODO
+    fn longer_cycle_throws_error() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = default_init_msg();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Anna -> Bert -> Carl -> Dora
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Bert".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Carl".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Dora".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Dora can't close the loop
+        let env = mock_env("Dora", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::DelegationCycle { delegate: HumanAddr("Anna".to_string()) });
+
+        // Someone else can still delegate into the chain
+        let env = mock_env("Emil", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Dora", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(5, value.options[0].count);
+    }
+
+    #[test]
+    fn delegation_depth_is_limited() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { max_delegation_depth: Some(2), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Rules are known up front
+        let res = query(&deps, QueryMsg::GetConfig {}).unwrap();
+        let value: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.max_delegation_depth);
+        assert_eq!(HumanAddr("creator".to_string()), value.admin);
+
+        // Anna -> Bert -> Carl
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Bert".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Carl".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Dora -> Anna -> Bert -> Carl is one delegate too many
+        let env = mock_env("Dora", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::DelegationTooDeep { max_depth: 2 });
+
+        // Dora -> Bert -> Carl is fine
+        let env = mock_env("Dora", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Bert".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+    }
+
+    #[test]
+    fn delegation_depth_is_limited_at_the_tail() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { max_delegation_depth: Some(2), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Anna -> Bert, then the chain grows at its tail: Bert -> Carl
+        for (delegator, delegate) in [("Anna", "Bert"), ("Bert", "Carl")].iter() {
+            let env = mock_env(*delegator, &coins(2, "token"));
+            let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr(delegate.to_string()) };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        // Carl -> Dora would put Anna's vote three delegates deep
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Dora".to_string()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::DelegationTooDeep { max_depth: 2 });
+
+        // Revoking doesn't lower the depth, Anna's place in the chain could be taken again
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::RevokeDelegation { poll_id: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Dora".to_string()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::DelegationTooDeep { max_depth: 2 });
+
+        // Carl can still vote, for Bert and himself
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.options[0].count);
+    }
+
+    #[test]
+    fn revoke_delegation() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = default_init_msg();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Dora -> Anna -> Bert
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Bert".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Dora", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Anna changes her mind, taking back her vote and Dora's
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::RevokeDelegation { poll_id: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.options[0].count);
+        assert_eq!(1, value.options[1].count);
+
+        // Dora's final delegate has voted, it is too late to revoke
+        let env = mock_env("Dora", &coins(2, "token"));
+        let msg = HandleMsg::RevokeDelegation { poll_id: 0 };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::DelegateAlreadyVoted { delegate: HumanAddr("Anna".to_string()) });
+
+        // Only delegated ballots can be revoked
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::RevokeDelegation { poll_id: 0 };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::NotDelegated {});
+    }
+
+    #[test]
+    fn revoking_too_deep_chain_throws_error() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { max_delegation_depth: Some(2), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Chains can't get this deep through delegations, the walk is bounded regardless
+        let chain = [("Anna", "Bert"), ("Bert", "Carl"), ("Carl", "Dora")];
+        for (index, (delegator, delegate)) in chain.iter().enumerate() {
+            let delegator_raw = deps.api.canonical_address(&HumanAddr(delegator.to_string())).unwrap();
+            let ballot = Ballot { has_voted: true, timestamp: 0, vote: None, delegate: Some(HumanAddr(delegate.to_string())), vote_value: index as u64 + 1, commitment: None, incoming_depth: index as u32 };
+            save_ballot(&mut deps.storage, 0, &delegator_raw, &ballot).unwrap();
+        }
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::RevokeDelegation { poll_id: 0 };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::DelegationTooDeep { max_depth: 2 });
+    }
+
+    #[test]
+    fn revoke_delegation_in_chain() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = default_init_msg();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Anna -> Bert -> Carl, and then Dora -> Anna
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Bert".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Carl".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Dora", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Bert revokes, and takes back the votes of Anna and Dora passing through him
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::RevokeDelegation { poll_id: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(3, value.options[0].count);
+        assert_eq!(1, value.options[1].count);
+    }
+
+    #[test]
+    fn override_delegate() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { allow_vote_override: true, ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Max -> Bert -> John, and John votes
+        let env = mock_env("Max", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Bert".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("John".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("John", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(3, value.options[0].count);
+
+        // Max disagrees with John and votes himself
+        let env = mock_env("Max", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.options[0].count);
+        assert_eq!(1, value.options[1].count);
+
+        // Max's vote is final now
+        let env = mock_env("Max", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Bert overrides too, which no longer includes Max's vote
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert_eq!(2, value.options[1].count);
+
+        // John's ballot no longer holds the votes taken back
+        let john_raw = deps.api.canonical_address(&HumanAddr("John".to_string())).unwrap();
+        assert_eq!(1, load_ballot(&deps.storage, 0, &john_raw).unwrap().vote_value);
+
+        // Including the ones delegated once he had voted
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("John".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(2, load_ballot(&deps.storage, 0, &john_raw).unwrap().vote_value);
+
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(1, load_ballot(&deps.storage, 0, &john_raw).unwrap().vote_value);
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert_eq!(3, value.options[1].count);
+    }
+
+    #[test]
+    fn override_is_opt_in() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = default_init_msg();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Max", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("John".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("John", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Max is locked out
+        let env = mock_env("Max", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.options[0].count);
+        assert_eq!(0, value.options[1].count);
+    }
+
+    // Storage that keeps track of the number of bytes read and written
+    struct CountingStorage {
+        inner: MockStorage,
+        bytes_read: Cell<usize>,
+        bytes_written: usize,
+    }
+
+    impl ReadonlyStorage for CountingStorage {
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+            let value = self.inner.get(key);
+            let value_len = value.as_ref().map(|v| v.len()).unwrap_or(0);
+            self.bytes_read.set(self.bytes_read.get() + key.len() + value_len);
+            value
+        }
+    }
+
+    impl Storage for CountingStorage {
+        fn set(&mut self, key: &[u8], value: &[u8]) {
+            self.bytes_written += key.len() + value.len();
+            self.inner.set(key, value)
+        }
+        fn remove(&mut self, key: &[u8]) {
+            self.inner.remove(key)
+        }
+    }
+
+    #[test]
+    fn vote_cost_does_not_grow_with_turnout() {
+        let mock = mock_dependencies(20, &coins(2, "token"));
+        let mut deps = Extern {
+            storage: CountingStorage { inner: MockStorage::new(), bytes_read: Cell::new(0), bytes_written: 0 },
+            api: mock.api,
+            querier: mock.querier,
+        };
+
+        let msg = default_init_msg();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Measure the storage accessed by each of a few hundred votes
+        let mut costs = vec![];
+        for i in 0..300 {
+            deps.storage.bytes_read.set(0);
+            deps.storage.bytes_written = 0;
+
+            let env = mock_env(HumanAddr(format!("voter{:03}", i)), &coins(2, "token"));
+            let msg = HandleMsg::Vote { poll_id: 0, option: i % 2 };
+            let _res = handle(&mut deps, env, msg).unwrap();
+
+            costs.push((deps.storage.bytes_read.get(), deps.storage.bytes_written));
+        }
+
+        // First vote also fixes the electorate, from then on the last vote costs as much as the second one
+        assert!(costs[1..].iter().all(|cost| *cost == costs[1]));
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(150, value.options[0].count);
+        assert_eq!(150, value.options[1].count);
+    }
+
+    #[test]
+    fn bad_vote_throws_error() {
+        // Max can't vote and delegate to John at the same time
+        let msg = br#"{"vote":{"option":0},"delegate":{"delegate":"John"}}"#;
+        let res: StdResult<HandleMsg> = from_slice(msg);
+        assert!(res.is_err());
+
+        // Nor can he send an empty ballot
+        let res: StdResult<HandleMsg> = from_slice(b"{}");
+        assert!(res.is_err());
+    }
+
+
+    #[test]
+    fn errors_have_codes() {
+        match StdError::from(ContractError::NotEligible {}) {
+            StdError::GenericErr { msg, .. } => {
+                assert_eq!(msg, r#"{"code":6,"message":"Sender is not part of the electorate"}"#)
+            }
+            _ => panic!("Contract errors must be generic errors"),
+        }
+    }
+
+    #[test]
+    fn multiple_options() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let options = vec![String::from("roses"), String::from("vegetables"), String::from("pond")];
+        let msg = InitMsg { poll: String::from("Which garden design?"), options, ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 2 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Max delegates to John, who picks the vegetables
+        let env = mock_env("Max", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("John".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("John", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(value.options, vec![
+            OptionTally { label: String::from("roses"), count: 0 },
+            OptionTally { label: String::from("vegetables"), count: 2 },
+            OptionTally { label: String::from("pond"), count: 1 },
+        ]);
+    }
+
+    #[test]
+    fn unknown_option_throws_error() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { poll: String::from("Is the sky blue?"), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 2 };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::MalformedBallot { reason: String::from("option 2 does not exist") });
+    }
+
+    #[test]
+    fn poll_needs_two_options() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { poll: String::from("Is the sky blue?"), options: vec![String::from("yes")], ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let res = init(&mut deps, env, msg);
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn restricted_electorate() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let electorate = vec![HumanAddr("Max".to_string()), HumanAddr("John".to_string())];
+        let msg = InitMsg { electorate: Some(electorate), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Outsiders can neither vote nor delegate
+        let env = mock_env("outsider", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::NotEligible {});
+
+        // Members can't delegate to outsiders
+        let env = mock_env("Max", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("outsider".to_string()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::IneligibleDelegate { delegate: HumanAddr("outsider".to_string()) });
+
+        // Members can vote
+        let env = mock_env("John", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert_eq!(0, value.options[1].count);
+    }
+
+    #[test]
+    fn removing_members_keeps_electorate_open() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = default_init_msg();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::RemoveElectorate { members: vec![HumanAddr("Max".to_string())] };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetConfig {}).unwrap();
+        let value: ConfigResponse = from_binary(&res).unwrap();
+        assert!(!value.restricted_electorate);
+
+        let env = mock_env("John", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+    }
+
+    #[test]
+    fn admin_updates_electorate() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let electorate = vec![HumanAddr("Max".to_string())];
+        let msg = InitMsg { electorate: Some(electorate), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Only the admin can change the electorate
+        let env = mock_env("Max", &coins(2, "token"));
+        let msg = HandleMsg::AddElectorate { members: vec![HumanAddr("John".to_string())] };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::NotAdmin {});
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::AddElectorate { members: vec![HumanAddr("John".to_string())] };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::RemoveElectorate { members: vec![HumanAddr("Max".to_string())] };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Max was removed
+        let env = mock_env("Max", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let res = handle(&mut deps, env, msg);
+        assert!(res.is_err());
+
+        // John was added
+        let env = mock_env("John", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Once voting started, the electorate is fixed, even for polls without ballots
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::CreatePoll { poll: String::from("Should we plant trees?"), options: yes_no(), duration: STANDARD_DURATION, early_results_allowed: true, topic: None, reveal_duration: None, quorum: None, threshold: None, method: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::AddElectorate { members: vec![HumanAddr("Max".to_string())] };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::ElectorateLocked { reason: String::from("a poll with ballots is open") });
+
+        // Until the poll is closed
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::AddElectorate { members: vec![HumanAddr("Max".to_string())] };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Max", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 1, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Results of the closed poll keep the electorate it was held in
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 0 }).unwrap();
+        let value: ResultResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(1), value.electorate_size);
+
+        let res = query(&deps, QueryMsg::GetConfig {}).unwrap();
+        let value: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.electorate_size);
+
+        // A poll that ended keeps the electorate fixed until it is closed, even without ballots
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 1, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::CreatePoll { poll: String::from("Should we paint the benches?"), options: yes_no(), duration: STANDARD_DURATION, early_results_allowed: true, topic: None, reveal_duration: None, quorum: None, threshold: None, method: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let mut env = mock_env("creator", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::RemoveElectorate { members: vec![HumanAddr("Max".to_string())] };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::ElectorateLocked { reason: String::from("a poll that ended is waiting to be closed") });
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 2, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let mut env = mock_env("creator", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::RemoveElectorate { members: vec![HumanAddr("Max".to_string())] };
+        let _res = handle(&mut deps, env, msg).unwrap();
+    }
+
+    #[test]
+    fn mini_public() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let candidates: Vec<HumanAddr> = ["Anna", "Bert", "Carl", "Dora", "Emil"].iter()
+            .map(|name| HumanAddr(name.to_string()))
+            .collect();
+        let seed = Binary(b"neighborhood".to_vec());
+        let mini_public = MiniPublicInit {
+            candidates: candidates.clone(),
+            sample_size: 2,
+            seed_commitment: Binary(sha_256(seed.as_slice()).to_vec()),
+        };
+        let msg = InitMsg { mini_public: Some(mini_public), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Nobody can vote before the draw
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let res = handle(&mut deps, env, msg);
+        assert!(res.is_err());
+
+        // Seed has to match the commitment
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::DrawMiniPublic { seed: Binary(b"something else".to_vec()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::SeedMismatch {});
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::DrawMiniPublic { seed: seed.clone() };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Anyone can reproduce the draw from the query
+        let res = query(&deps, QueryMsg::GetMiniPublic {}).unwrap();
+        let value: MiniPublicResponse = from_binary(&res).unwrap();
+        assert_eq!(value.seed, Some(seed));
+        let selected = value.selected.unwrap();
+        assert_eq!(selected.len(), 2);
+        let reproduced = draw_sample(&value.candidates, value.sample_size, value.seed.unwrap().as_slice(),
+            value.block_height.unwrap(), value.block_time.unwrap());
+        assert_eq!(selected, reproduced);
+
+        // Only the selected can vote
+        for candidate in candidates.iter() {
+            let env = mock_env(candidate.clone(), &coins(2, "token"));
+            let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+            let res = handle(&mut deps, env, msg);
+            assert_eq!(res.is_ok(), selected.contains(candidate));
+        }
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.options[0].count);
+    }
+
+    #[test]
+    fn mini_public_candidates_are_unique() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let candidates: Vec<HumanAddr> = ["Anna", "Bert", "Anna"].iter()
+            .map(|name| HumanAddr(name.to_string()))
+            .collect();
+        let seed = Binary(b"neighborhood".to_vec());
+        let mini_public = MiniPublicInit { candidates: candidates.clone(), sample_size: 3, seed_commitment: Binary(sha_256(seed.as_slice()).to_vec()) };
+        let msg = InitMsg { mini_public: Some(mini_public), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let res = init(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::InvalidPoll { reason: String::from("sample size must be between 1 and the number of candidates") });
+
+        let mini_public = MiniPublicInit { candidates, sample_size: 2, seed_commitment: Binary(sha_256(seed.as_slice()).to_vec()) };
+        let msg = InitMsg { mini_public: Some(mini_public), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::DrawMiniPublic { seed };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetMiniPublic {}).unwrap();
+        let value: MiniPublicResponse = from_binary(&res).unwrap();
+        assert_eq!(vec![HumanAddr("Anna".to_string()), HumanAddr("Bert".to_string())], value.candidates);
+        assert_eq!(2, value.selected.unwrap().len());
+
+        let res = query(&deps, QueryMsg::GetConfig {}).unwrap();
+        let value: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.electorate_size);
+    }
+
+    #[test]
+    fn no_more_voting_after_end() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { poll: String::from("Is the sky blue?"), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let res = handle(&mut deps, env, msg).unwrap();
+        let answer: HandleAnswer = from_binary(&res.data.unwrap()).unwrap();
+        match answer {
+            HandleAnswer::Ballot { status: Failure, error_code, .. } => assert_eq!(Some(2), error_code),
+            _ => panic!("Must disallow votes after end"),
+        }
+
+        // Late voters don't close the tally, which is left to close_tally, but clients know voting ended
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(0, value.options[0].count);
+        assert!(!value.is_completed);
+        assert_eq!(mock_env("creator", &coins(2, "token")).block.time + STANDARD_DURATION, value.end_timestamp);
+    }
+
+    #[test]
+    fn secret_tally_is_revealed_after_end() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { poll: String::from("Is the sky blue?"), early_results_allowed: false, ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Results are sealed while the tally is ongoing
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 });
+        assert_contract_error(res, ContractError::ResultsSealed {});
+
+        // Tally can't be closed early
+        let env = mock_env("anyone", &coins(2, "token"));
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::PollOngoing {});
+
+        // Anyone can close it after the end, without voting
+        let mut env = mock_env("someone else", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert!(value.is_completed);
+    }
+
+    #[test]
+    fn multiple_polls() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let electorate = vec![HumanAddr("Anna".to_string()), HumanAddr("Bert".to_string())];
+        let msg = InitMsg { electorate: Some(electorate), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Members put new polls on the agenda, outsiders can't
+        let env = mock_env("outsider", &coins(2, "token"));
+        let msg = HandleMsg::CreatePoll { poll: String::from("Where should the benches go?"), options: vec![String::from("Park"), String::from("Square")], duration: STANDARD_DURATION, early_results_allowed: true, topic: None, reveal_duration: None, quorum: None, threshold: None, method: None };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::NotEligible {});
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::CreatePoll { poll: String::from("Where should the benches go?"), options: vec![String::from("Park"), String::from("Square")], duration: STANDARD_DURATION, early_results_allowed: true, topic: None, reveal_duration: None, quorum: None, threshold: None, method: None };
+        let res = handle(&mut deps, env, msg).unwrap();
+        let answer: HandleAnswer = from_binary(&res.data.unwrap()).unwrap();
+        match answer {
+            HandleAnswer::CreatePoll { poll_id: 1, .. } => {}
+            _ => panic!("Second poll must have id 1"),
+        }
+
+        let res = query(&deps, QueryMsg::GetPoll { poll_id: 1 }).unwrap();
+        let value: PollResponse = from_binary(&res).unwrap();
+        assert_eq!("Where should the benches go?", value.poll);
+        assert_eq!(vec![String::from("Park"), String::from("Square")], value.options);
+
+        // Ballots of different polls don't interfere
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 1, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 1, delegate: HumanAddr("Anna".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert_eq!(0, value.options[1].count);
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 1 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(0, value.options[0].count);
+        assert_eq!(2, value.options[1].count);
+
+        // Polls that weren't created can't be used
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 2, option: 0 };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::UnknownPoll { poll_id: 2 });
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 2 });
+        assert_contract_error(res, ContractError::UnknownPoll { poll_id: 2 });
+    }
+
+    #[test]
+    fn list_polls() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { poll: String::from("Poll 0"), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Every other poll ends right away
+        for i in 1..5 {
+            let env = mock_env("creator", &coins(2, "token"));
+            let duration = if i % 2 == 0 { STANDARD_DURATION } else { 0 };
+            let msg = HandleMsg::CreatePoll { poll: format!("Poll {}", i), options: yes_no(), duration, early_results_allowed: true, topic: None, reveal_duration: None, quorum: None, threshold: None, method: None };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+        for poll_id in [1, 3].iter() {
+            let mut env = mock_env("anyone", &coins(2, "token"));
+            env.block.time += 1;
+            let msg = HandleMsg::CloseTally { poll_id: *poll_id, limit: None };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        let res = query(&deps, QueryMsg::GetConfig {}).unwrap();
+        let value: ConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(5, value.poll_count);
+
+        // Pages follow the ids
+        let res = query(&deps, QueryMsg::ListPolls { status: None, start_after: None, limit: Some(2) }).unwrap();
+        let value: ListPollsResponse = from_binary(&res).unwrap();
+        assert_eq!(vec![0, 1], value.polls.iter().map(|poll| poll.poll_id).collect::<Vec<u64>>());
+
+        let res = query(&deps, QueryMsg::ListPolls { status: None, start_after: Some(1), limit: Some(2) }).unwrap();
+        let value: ListPollsResponse = from_binary(&res).unwrap();
+        assert_eq!(vec![2, 3], value.polls.iter().map(|poll| poll.poll_id).collect::<Vec<u64>>());
+        assert_eq!("Poll 2", value.polls[0].poll);
+
+        // Filtered by status
+        let res = query(&deps, QueryMsg::ListPolls { status: Some(PollStatus::Open), start_after: None, limit: None }).unwrap();
+        let value: ListPollsResponse = from_binary(&res).unwrap();
+        assert_eq!(vec![0, 2, 4], value.polls.iter().map(|poll| poll.poll_id).collect::<Vec<u64>>());
+
+        let res = query(&deps, QueryMsg::ListPolls { status: Some(PollStatus::Closed), start_after: Some(1), limit: None }).unwrap();
+        let value: ListPollsResponse = from_binary(&res).unwrap();
+        assert_eq!(vec![3], value.polls.iter().map(|poll| poll.poll_id).collect::<Vec<u64>>());
+        assert!(value.polls[0].is_completed);
+        assert_eq!(None, value.next_start_after);
+
+        // A listing only reads so many polls, and tells where to go on
+        for i in 5..105 {
+            let env = mock_env("creator", &coins(2, "token"));
+            let msg = HandleMsg::CreatePoll { poll: format!("Poll {}", i), options: yes_no(), duration: STANDARD_DURATION, early_results_allowed: true, topic: None, reveal_duration: None, quorum: None, threshold: None, method: None };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+        let res = query(&deps, QueryMsg::ListPolls { status: None, start_after: None, limit: Some(2) }).unwrap();
+        let value: ListPollsResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(1), value.next_start_after);
+
+        let res = query(&deps, QueryMsg::ListPolls { status: Some(PollStatus::Closed), start_after: Some(3), limit: None }).unwrap();
+        let value: ListPollsResponse = from_binary(&res).unwrap();
+        assert!(value.polls.is_empty());
+        assert_eq!(Some(103), value.next_start_after);
+
+        let res = query(&deps, QueryMsg::ListPolls { status: Some(PollStatus::Closed), start_after: Some(103), limit: None }).unwrap();
+        let value: ListPollsResponse = from_binary(&res).unwrap();
+        assert!(value.polls.is_empty());
+        assert_eq!(None, value.next_start_after);
+    }
+
+    #[test]
+    fn standing_delegation() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { electorate: neighbors(), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Anna lets Bert vote for her, John asked Anna to vote for him in the first poll
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Bert".to_string()), topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Standing delegates are only shown to their delegator
+        let res = query(&deps, QueryMsg::GetStandingDelegate { address: HumanAddr("Anna".to_string()), key: String::from("anna's key") });
+        assert_contract_error(res, ContractError::WrongViewingKey {});
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::SetViewingKey { key: String::from("anna's key") };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetStandingDelegate { address: HumanAddr("Anna".to_string()), key: String::from("anna's key") }).unwrap();
+        let value: StandingDelegateResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(HumanAddr("Bert".to_string())), value.delegate);
+
+        let env = mock_env("John", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Standing delegations are resolved when the tally is closed
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[1].count);
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(0, value.options[0].count);
+        assert_eq!(3, value.options[1].count);
+
+        // In later polls, voting directly takes precedence
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::CreatePoll { poll: String::from("Should we plant trees?"), options: yes_no(), duration: STANDARD_DURATION, early_results_allowed: true, topic: None, reveal_duration: None, quorum: None, threshold: None, method: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 1, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 1, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 1, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 1 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert_eq!(1, value.options[1].count);
+
+        // Once cleared, the standing delegate no longer votes for Anna
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::ClearStandingDelegate { topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetStandingDelegate { address: HumanAddr("Anna".to_string()), key: String::from("anna's key") }).unwrap();
+        let value: StandingDelegateResponse = from_binary(&res).unwrap();
+        assert_eq!(None, value.delegate);
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::ClearStandingDelegate { topic: None };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::NotDelegated {});
+    }
+
+    #[test]
+    fn standing_delegates_changed_after_the_end_are_ignored() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { electorate: neighbors(), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Bert".to_string()), topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Dora", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Bert".to_string()), topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Once voting ended, Anna switches to Carl and Emil follows Carl, before the tally is closed
+        let mut env = mock_env("Anna", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Carl".to_string()), topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let mut env = mock_env("Emil", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Carl".to_string()), topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 2;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Only Dora's standing delegation applies
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.options[0].count);
+        assert_eq!(1, value.options[1].count);
+    }
+
+    #[test]
+    fn close_tally_in_batches() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { electorate: neighbors(), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Anna sets her standing delegate twice, and is still resolved only once
+        let standing = [("Anna", None), ("Carl", None), ("Anna", Some(String::from("budget"))), ("Dora", None)];
+        for (delegator, topic) in standing.iter() {
+            let env = mock_env(*delegator, &coins(2, "token"));
+            let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Bert".to_string()), topic: topic.clone() };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::ClearStandingDelegate { topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Bert".to_string()), topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Carl leaves, and Emil takes his place in the list of delegators
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::ClearStandingDelegate { topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let env = mock_env("Emil", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Bert".to_string()), topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        assert_eq!(3, standing_delegator_count(&deps.storage).unwrap());
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: Some(2) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 0 });
+        assert_contract_error(res, ContractError::PollOngoing {});
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: Some(2) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 0 }).unwrap();
+        let value: ResultResponse = from_binary(&res).unwrap();
+        assert_eq!(Outcome::Passed, value.outcome);
+        assert_eq!(4, value.turnout);
+    }
+
+    #[test]
+    fn standing_delegates_need_restricted_electorate() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = default_init_msg();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Bert".to_string()), topic: None };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::OpenElectorate {});
+
+        // Members removed from the electorate lose their standing delegates
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { electorate: neighbors(), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Bert".to_string()), topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::RemoveElectorate { members: vec![HumanAddr("Anna".to_string())] };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let anna_raw = deps.api.canonical_address(&HumanAddr("Anna".to_string())).unwrap();
+        assert!(may_load_standing_delegates(&deps.storage, &anna_raw).unwrap().is_none());
+        assert_eq!(None, may_load_standing_delegator(&deps.storage, 0).unwrap());
+    }
+
+    #[test]
+    fn standing_delegation_cycle_throws_error() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { electorate: neighbors(), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Bert".to_string()), topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Carl".to_string()), topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Carl -> Anna -> Bert -> Carl
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Anna".to_string()), topic: None };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::DelegationCycle { delegate: HumanAddr("Anna".to_string()) });
+
+        // Ballots follow the chain up to Carl, who votes
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(3, value.options[0].count);
+    }
+
+    #[test]
+    fn topic_delegation() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { electorate: neighbors(), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        for topic in ["budget", "childcare"].iter() {
+            let env = mock_env("creator", &coins(2, "token"));
+            let msg = HandleMsg::CreatePoll { poll: format!("Question on {}", topic), options: yes_no(), duration: STANDARD_DURATION, early_results_allowed: true, topic: Some(topic.to_string()), reveal_duration: None, quorum: None, threshold: None, method: None };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::SetViewingKey { key: String::from("anna's key") };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Anna trusts Carl on the budget, and Bert on everything else
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Bert".to_string()), topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Carl".to_string()), topic: Some(String::from("budget")) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetStandingDelegate { address: HumanAddr("Anna".to_string()), key: String::from("anna's key") }).unwrap();
+        let value: StandingDelegateResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(HumanAddr("Bert".to_string())), value.delegate);
+        assert_eq!(vec![TopicDelegate { topic: String::from("budget"), delegate: HumanAddr("Carl".to_string()) }], value.topics);
+
+        // Bert votes yes and Carl votes no everywhere
+        for poll_id in 0..3 {
+            let env = mock_env("Bert", &coins(2, "token"));
+            let msg = HandleMsg::Vote { poll_id, option: 0 };
+            let _res = handle(&mut deps, env, msg).unwrap();
+
+            let env = mock_env("Carl", &coins(2, "token"));
+            let msg = HandleMsg::Vote { poll_id, option: 1 };
+            let _res = handle(&mut deps, env, msg).unwrap();
+
+            let mut env = mock_env("anyone", &coins(2, "token"));
+            env.block.time += STANDARD_DURATION + 1;
+            let msg = HandleMsg::CloseTally { poll_id, limit: None };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        // Only the poll on the budget follows Carl
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.options[0].count);
+        assert_eq!(1, value.options[1].count);
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 1 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert_eq!(2, value.options[1].count);
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 2 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(2, value.options[0].count);
+        assert_eq!(1, value.options[1].count);
+
+        // Clearing the topic leaves the default delegate in place
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::ClearStandingDelegate { topic: Some(String::from("budget")) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetStandingDelegate { address: HumanAddr("Anna".to_string()), key: String::from("anna's key") }).unwrap();
+        let value: StandingDelegateResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(HumanAddr("Bert".to_string())), value.delegate);
+        assert!(value.topics.is_empty());
+    }
+
+    #[test]
+    fn viewing_keys() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = default_init_msg();
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::CreateViewingKey { entropy: String::from("random words") };
+        let res = handle(&mut deps, env, msg).unwrap();
+        let key = match from_binary(&res.data.unwrap()).unwrap() {
+            HandleAnswer::CreateViewingKey { key } => key.0,
+            _ => panic!("Must return the viewing key"),
+        };
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::SetViewingKey { key: String::from("bert's key") };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Bert delegates to Anna, who votes afterwards
+        let res = query(&deps, QueryMsg::GetMyBallot { poll_id: 0, address: HumanAddr("Anna".to_string()), key: key.clone() }).unwrap();
+        let value: MyBallotResponse = from_binary(&res).unwrap();
+        assert_eq!(MyBallotResponse { has_voted: false, vote: None, delegate: None, vote_value: 1 }, value);
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 1 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetMyBallot { poll_id: 0, address: HumanAddr("Anna".to_string()), key: key.clone() }).unwrap();
+        let value: MyBallotResponse = from_binary(&res).unwrap();
+        assert_eq!(MyBallotResponse { has_voted: true, vote: Some(Vote::Option(1)), delegate: None, vote_value: 2 }, value);
+
+        let res = query(&deps, QueryMsg::GetMyBallot { poll_id: 0, address: HumanAddr("Bert".to_string()), key: String::from("bert's key") }).unwrap();
+        let value: MyBallotResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(HumanAddr("Anna".to_string())), value.delegate);
+
+        // Keys only open the ballots of their owner
+        let res = query(&deps, QueryMsg::GetMyBallot { poll_id: 0, address: HumanAddr("Bert".to_string()), key });
+        assert_contract_error(res, ContractError::WrongViewingKey {});
+
+        let res = query(&deps, QueryMsg::GetMyBallot { poll_id: 0, address: HumanAddr("Carl".to_string()), key: String::from("bert's key") });
+        assert_contract_error(res, ContractError::WrongViewingKey {});
+    }
+
+    #[test]
+    fn query_permits() {
+        use crate::permit::{Permit, PermitParams, PermitSignature, PubKey};
+        use crate::state::StandingDelegates;
+        use secret_toolkit::crypto::secp256k1::PrivateKey;
+
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = default_init_msg();
+        let env = mock_env("creator", &coins(2, "token"));
+        let contract_address = env.contract.address.clone();
+        let chain_id = env.block.chain_id.clone();
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Permit signed by a wallet, whose key gives an address the mock API can display
+        let mut raw_key = [7u8; 32];
+        raw_key[24..].copy_from_slice(&186_848u64.to_be_bytes());
+        let private_key = PrivateKey::parse(&raw_key).unwrap();
+        let sign = |params: PermitParams| {
+            let mut permit = Permit {
+                params,
+                signature: PermitSignature {
+                    pub_key: PubKey { r#type: String::from("tendermint/PubKeySecp256k1"), value: Binary(private_key.pubkey().serialize_compressed().to_vec()) },
+                    signature: Binary(vec![]),
+                },
+            };
+            permit.signature.signature = Binary(private_key.sign(&permit.sign_bytes().unwrap()).serialize().to_vec());
+            permit
+        };
+        let params = PermitParams { allowed_tokens: vec![contract_address.clone()], permit_name: String::from("quartier app"), chain_id: chain_id.clone(), permissions: vec![Permission::Ballot] };
+        let permit = sign(params.clone());
+
+        // Ballot of the signer, as if it had voted
+        let signer_raw = permit::validate(&deps.storage, &permit, &contract_address, &chain_id, Permission::Ballot).unwrap();
+        let ballot = Ballot { has_voted: true, timestamp: 0, vote: Some(Vote::Option(1)), delegate: None, vote_value: 1, commitment: None, incoming_depth: 0 };
+        save_ballot(&mut deps.storage, 0, &signer_raw, &ballot).unwrap();
+
+        let msg = QueryMsg::WithPermit { permit: permit.clone(), query: QueryWithPermit::GetMyBallot { poll_id: 0 } };
+        let res = query(&deps, msg).unwrap();
+        let value: MyBallotResponse = from_binary(&res).unwrap();
+        assert_eq!(MyBallotResponse { has_voted: true, vote: Some(Vote::Option(1)), delegate: None, vote_value: 1 }, value);
+
+        let delegates = StandingDelegates { default: Some(deps.api.canonical_address(&HumanAddr("Bert".to_string())).unwrap()), topics: vec![], updated: 0 };
+        save_standing_delegates(&mut deps.storage, &signer_raw, &delegates).unwrap();
+        let msg = QueryMsg::WithPermit { permit: permit.clone(), query: QueryWithPermit::GetStandingDelegate {} };
+        let res = query(&deps, msg).unwrap();
+        let value: StandingDelegateResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(HumanAddr("Bert".to_string())), value.delegate);
+
+        // Permits are bound to what was signed
+        let mut tampered = permit.clone();
+        tampered.params.permit_name = String::from("other name");
+        let msg = QueryMsg::WithPermit { permit: tampered, query: QueryWithPermit::GetMyBallot { poll_id: 0 } };
+        let res = query(&deps, msg);
+        assert_contract_error(res, ContractError::PermitRejected { reason: String::from("signature is invalid") });
+
+        let other_contract = sign(PermitParams { allowed_tokens: vec![HumanAddr("other contract".to_string())], ..params.clone() });
+        let msg = QueryMsg::WithPermit { permit: other_contract, query: QueryWithPermit::GetMyBallot { poll_id: 0 } };
+        let res = query(&deps, msg);
+        assert_contract_error(res, ContractError::PermitRejected { reason: format!("permit doesn't apply to {}", contract_address) });
+
+        let no_permission = sign(PermitParams { permissions: vec![], ..params.clone() });
+        let msg = QueryMsg::WithPermit { permit: no_permission, query: QueryWithPermit::GetMyBallot { poll_id: 0 } };
+        let res = query(&deps, msg);
+        assert_contract_error(res, ContractError::PermitRejected { reason: String::from("permit doesn't allow this query") });
+
+        let other_chain = sign(PermitParams { chain_id: String::from("secret-2"), ..params.clone() });
+        let msg = QueryMsg::WithPermit { permit: other_chain, query: QueryWithPermit::GetMyBallot { poll_id: 0 } };
+        let res = query(&deps, msg);
+        assert_contract_error(res, ContractError::PermitRejected { reason: String::from("permit was signed for chain secret-2") });
+
+        let mut malformed = permit.clone();
+        malformed.signature.pub_key.value = Binary(vec![2u8; 12]);
+        let msg = QueryMsg::WithPermit { permit: malformed, query: QueryWithPermit::GetMyBallot { poll_id: 0 } };
+        let res = query(&deps, msg);
+        assert_contract_error(res, ContractError::PermitRejected { reason: String::from("malformed public key") });
+
+        let mut malformed = permit.clone();
+        malformed.signature.signature = Binary(vec![1u8; 12]);
+        let msg = QueryMsg::WithPermit { permit: malformed, query: QueryWithPermit::GetMyBallot { poll_id: 0 } };
+        let res = query(&deps, msg);
+        assert_contract_error(res, ContractError::PermitRejected { reason: String::from("malformed signature") });
+
+        // Revoked permits are no longer accepted, permits are only revoked by their signer
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::RevokePermit { permit_name: String::from("quartier app") };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let msg = QueryMsg::WithPermit { permit: permit.clone(), query: QueryWithPermit::GetMyBallot { poll_id: 0 } };
+        let _res = query(&deps, msg).unwrap();
+
+        let signer = deps.api.human_address(&signer_raw).unwrap();
+        let env = mock_env(signer, &coins(2, "token"));
+        let msg = HandleMsg::RevokePermit { permit_name: String::from("quartier app") };
+        let _res = handle(&mut deps, env, msg).unwrap();
+        let msg = QueryMsg::WithPermit { permit, query: QueryWithPermit::GetMyBallot { poll_id: 0 } };
+        let res = query(&deps, msg);
+        assert_contract_error(res, ContractError::PermitRejected { reason: String::from("permit quartier app was revoked") });
+    }
+
+    fn commitment(option: u32, salt: &[u8]) -> Binary {
+        Binary(sha_256(&[&option.to_be_bytes()[..], salt].concat()).to_vec())
+    }
+
+    #[test]
+    fn commit_reveal() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { reveal_duration: Some(STANDARD_DURATION), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        // Plain votes are refused
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::MalformedBallot { reason: String::from("votes of this poll have to be committed") });
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::CommitVote { poll_id: 0, commitment: commitment(0, b"anna's salt") };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::CommitVote { poll_id: 0, commitment: commitment(1, b"bert's salt") };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::CommitVote { poll_id: 0, commitment: commitment(1, b"anna's salt") };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::AlreadyVoted {});
+
+        // Votes can't be revealed while voting is ongoing
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::RevealVote { poll_id: 0, option: 0, salt: Binary(b"anna's salt".to_vec()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::NotRevealing {});
+
+        // Nor committed once it ended
+        let mut env = mock_env("Carl", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CommitVote { poll_id: 0, commitment: commitment(0, b"carl's salt") };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::PollClosed {});
+
+        let mut env = mock_env("Anna", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::RevealVote { poll_id: 0, option: 1, salt: Binary(b"anna's salt".to_vec()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::CommitmentMismatch {});
+
+        let mut env = mock_env("Anna", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::RevealVote { poll_id: 0, option: 0, salt: Binary(b"anna's salt".to_vec()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let mut env = mock_env("Carl", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::RevealVote { poll_id: 0, option: 0, salt: Binary(b"carl's salt".to_vec()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::NoCommitment {});
+
+        // The tally can only be closed after the reveal window, Bert's vote is never counted
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::PollOngoing {});
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += 2 * STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let mut env = mock_env("Bert", &coins(2, "token"));
+        env.block.time += 2 * STANDARD_DURATION + 1;
+        let msg = HandleMsg::RevealVote { poll_id: 0, option: 1, salt: Binary(b"bert's salt".to_vec()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::PollClosed {});
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert_eq!(0, value.options[1].count);
+        assert!(value.is_completed);
+    }
+
+    #[test]
+    fn commit_reveal_delegation() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { reveal_duration: Some(STANDARD_DURATION), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let msg = HandleMsg::CreatePoll { poll: String::from("Should we plant trees?"), options: yes_no(), duration: STANDARD_DURATION, early_results_allowed: true, topic: None, reveal_duration: Some(STANDARD_DURATION), quorum: None, threshold: None, method: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Dora delegates before Anna commits, Emil after, in both polls
+        for poll_id in 0..2 {
+            let env = mock_env("Dora", &coins(2, "token"));
+            let msg = HandleMsg::Delegate { poll_id, delegate: HumanAddr("Anna".to_string()) };
+            let _res = handle(&mut deps, env, msg).unwrap();
+
+            let env = mock_env("Anna", &coins(2, "token"));
+            let msg = HandleMsg::CommitVote { poll_id, commitment: commitment(1, b"anna's salt") };
+            let _res = handle(&mut deps, env, msg).unwrap();
+
+            let env = mock_env("Emil", &coins(2, "token"));
+            let msg = HandleMsg::Delegate { poll_id, delegate: HumanAddr("Anna".to_string()) };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        // A committed delegate counts as having voted
+        let env = mock_env("Dora", &coins(2, "token"));
+        let msg = HandleMsg::RevokeDelegation { poll_id: 0 };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::DelegateAlreadyVoted { delegate: HumanAddr("Anna".to_string()) });
+
+        // Anna only reveals her vote in the first poll
+        let mut env = mock_env("Anna", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::RevealVote { poll_id: 0, option: 1, salt: Binary(b"anna's salt".to_vec()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        for poll_id in 0..2 {
+            let mut env = mock_env("anyone", &coins(2, "token"));
+            env.block.time += 2 * STANDARD_DURATION + 1;
+            let msg = HandleMsg::CloseTally { poll_id, limit: None };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(3, value.options[1].count);
+
+        // Votes delegated to an unrevealed ballot are lost
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 1 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(0, value.options[1].count);
+    }
+
+    #[test]
+    fn poll_results() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let electorate = vec![HumanAddr("Anna".to_string()), HumanAddr("Bert".to_string()), HumanAddr("Carl".to_string()), HumanAddr("Dora".to_string())];
+        let quorum = Some(Quorum::Fraction { numerator: 1, denominator: 2 });
+        let threshold = Some(Threshold::Fraction { numerator: 2, denominator: 3 });
+        let msg = InitMsg { electorate: Some(electorate), quorum, threshold, ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        for _ in 0..2 {
+            let env = mock_env("creator", &coins(2, "token"));
+            let msg = HandleMsg::CreatePoll { poll: String::from("Should we plant trees?"), options: yes_no(), duration: STANDARD_DURATION, early_results_allowed: true, topic: None, reveal_duration: None, quorum, threshold, method: None };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        // Poll 0 passes, nobody else shows up in poll 1, and poll 2 is split
+        let votes = vec![(0, "Anna", 0), (0, "Bert", 0), (0, "Carl", 1), (1, "Anna", 0), (2, "Anna", 0), (2, "Bert", 1)];
+        for (poll_id, voter, option) in votes {
+            let env = mock_env(voter, &coins(2, "token"));
+            let msg = HandleMsg::Vote { poll_id, option };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 0 });
+        assert_contract_error(res, ContractError::PollOngoing {});
+
+        for poll_id in 0..3 {
+            let mut env = mock_env("anyone", &coins(2, "token"));
+            env.block.time += STANDARD_DURATION + 1;
+            let msg = HandleMsg::CloseTally { poll_id, limit: None };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 0 }).unwrap();
+        let value: ResultResponse = from_binary(&res).unwrap();
+        assert_eq!(Outcome::Passed, value.outcome);
+        assert_eq!(Some(0), value.winner);
+        assert_eq!(3, value.turnout);
+        assert_eq!(Some(4), value.electorate_size);
+
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 1 }).unwrap();
+        let value: ResultResponse = from_binary(&res).unwrap();
+        assert_eq!(Outcome::QuorumNotReached, value.outcome);
+        assert_eq!(None, value.winner);
+
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 2 }).unwrap();
+        let value: ResultResponse = from_binary(&res).unwrap();
+        assert_eq!(Outcome::Rejected, value.outcome);
+        assert_eq!(2, value.turnout);
+    }
+
+    #[test]
+    fn quorum_needs_known_electorate() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let quorum = Some(Quorum::Fraction { numerator: 1, denominator: 2 });
+        let msg = InitMsg { quorum, ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let res = init(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::InvalidPoll { reason: String::from("quorum as a share of the electorate needs a restricted electorate") });
+
+        let threshold = Some(Threshold::Fraction { numerator: 3, denominator: 2 });
+        let msg = InitMsg { threshold, ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let res = init(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::InvalidPoll { reason: String::from("threshold must be a share between 0 and 1") });
+    }
+
+    #[test]
+    fn overflowing_durations_throw_error() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let msg = InitMsg { duration: u64::MAX, ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let res = init(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::InvalidPoll { reason: String::from("poll would end too far in the future") });
+
+        let msg = InitMsg { reveal_duration: Some(u64::MAX), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let res = init(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::InvalidPoll { reason: String::from("reveal period would end too far in the future") });
+    }
+
+    #[test]
+    fn abstain() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let quorum = Some(Quorum::Absolute { count: 4 });
+        let msg = InitMsg { electorate: neighbors(), quorum, ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Abstain { poll_id: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Delegating to someone who abstained is abstaining, within the poll or with a standing delegate
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Dora", &coins(2, "token"));
+        let msg = HandleMsg::SetStandingDelegate { delegate: HumanAddr("Anna".to_string()), topic: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Carl", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(1, value.options[0].count);
+        assert_eq!(2, value.abstentions);
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Abstentions make the quorum, but don't weigh against the leading option
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 0 }).unwrap();
+        let value: ResultResponse = from_binary(&res).unwrap();
+        assert_eq!(Outcome::Passed, value.outcome);
+        assert_eq!(Some(0), value.winner);
+        assert_eq!(4, value.turnout);
+    }
+
+    #[test]
+    fn ranked_choice() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let candidates = vec![String::from("Anna"), String::from("Carl"), String::from("Dora")];
+        let msg = InitMsg { poll: String::from("Who should be our treasurer?"), options: candidates, method: Some(VotingMethod::RankedChoice), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Vote { poll_id: 0, option: 0 };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::MalformedBallot { reason: String::from("ballot doesn't fit the voting method of the poll") });
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Rank { poll_id: 0, ranking: vec![0, 2, 0] };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::MalformedBallot { reason: String::from("option 0 is listed twice") });
+
+        // Emil's vote follows Dora's ranking
+        let env = mock_env("Emil", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Dora".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let rankings = vec![("Anna", vec![0, 2]), ("Bert", vec![0]), ("Gina", vec![0, 1]), ("Carl", vec![1, 0]), ("Dora", vec![2, 1]), ("Finn", vec![2, 0])];
+        for (voter, ranking) in rankings {
+            let env = mock_env(voter, &coins(2, "token"));
+            let msg = HandleMsg::Rank { poll_id: 0, ranking };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        // Tally shows first preferences
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(vec![3, 1, 3], value.options.iter().map(|option| option.count).collect::<Vec<u64>>());
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Carl is eliminated, and his vote goes to Anna
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 0 }).unwrap();
+        let value: ResultResponse = from_binary(&res).unwrap();
+        assert_eq!(vec![vec![3, 1, 3], vec![4, 0, 3]], value.rounds);
+        assert_eq!(Outcome::Passed, value.outcome);
+        assert_eq!(Some(0), value.winner);
+        assert_eq!(7, value.turnout);
+    }
+
+    #[test]
+    fn instant_runoff_ties() {
+        let rankings = vec![
+            RankedVotes { ranking: vec![0], votes: 2 },
+            RankedVotes { ranking: vec![1, 0], votes: 1 },
+            RankedVotes { ranking: vec![2, 0], votes: 1 },
+            RankedVotes { ranking: vec![3, 2], votes: 3 },
+        ];
+        // Options tied for the fewest votes go together
+        let runoff = instant_runoff(4, &rankings);
+        assert_eq!(vec![vec![2, 1, 1, 3], vec![4, 0, 0, 3]], runoff.rounds);
+
+        // Remaining options tied, without a majority
+        let rankings = vec![RankedVotes { ranking: vec![0], votes: 2 }, RankedVotes { ranking: vec![1], votes: 2 }];
+        let runoff = instant_runoff(3, &rankings);
+        assert_eq!(vec![vec![2, 2, 0], vec![2, 2, 0]], runoff.rounds);
+    }
+
+    #[test]
+    fn approval_voting() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let designs = vec![String::from("pond"), String::from("orchard"), String::from("playground")];
+        let msg = InitMsg { poll: String::from("Which garden designs do you like?"), options: designs, method: Some(VotingMethod::Approval), ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Approve { poll_id: 0, options: vec![] };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::MalformedBallot { reason: String::from("a ballot needs at least one option") });
+
+        // Bert's vote goes to both options Anna approves
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let approvals = vec![("Anna", vec![0, 1]), ("Carl", vec![1, 2]), ("Dora", vec![2])];
+        for (voter, options) in approvals {
+            let env = mock_env(voter, &coins(2, "token"));
+            let msg = HandleMsg::Approve { poll_id: 0, options };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(vec![2, 3, 2], value.options.iter().map(|option| option.count).collect::<Vec<u64>>());
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Three voters out of four approve the orchard
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 0 }).unwrap();
+        let value: ResultResponse = from_binary(&res).unwrap();
+        assert_eq!(Outcome::Passed, value.outcome);
+        assert_eq!(Some(1), value.winner);
+        assert_eq!(4, value.turnout);
+    }
+
+    #[test]
+    fn quadratic_voting() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let method = Some(VotingMethod::Quadratic { credits: 9 });
+        let msg = InitMsg { poll: String::from("How should we spend the budget?"), options: vec![String::from("benches"), String::from("trees")], method, ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Allocate { poll_id: 0, votes: vec![OptionVotes { option: 0, votes: 3 }] };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Allocate { poll_id: 0, votes: vec![OptionVotes { option: 0, votes: 3 }, OptionVotes { option: 1, votes: 1 }] };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::MalformedBallot { reason: String::from("votes cost 10 credits, the ballot holds 9") });
+
+        // Carl and Dora transfer their credits to Emil, who can then spend 27 of them
+        for delegator in ["Carl", "Dora"].iter() {
+            let env = mock_env(*delegator, &coins(2, "token"));
+            let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Emil".to_string()) };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        let env = mock_env("Emil", &coins(2, "token"));
+        let msg = HandleMsg::Allocate { poll_id: 0, votes: vec![OptionVotes { option: 1, votes: 5 }] };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Anna has spent her credits, there's no one left to spend Finn's
+        let env = mock_env("Finn", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::DelegateAlreadyVoted { delegate: HumanAddr("Anna".to_string()) });
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 0 }).unwrap();
+        let value: ResultResponse = from_binary(&res).unwrap();
+        assert_eq!(vec![3, 5], value.options.iter().map(|option| option.count).collect::<Vec<u64>>());
+        assert_eq!(4, value.turnout);
+        assert_eq!(Outcome::Passed, value.outcome);
+        assert_eq!(Some(1), value.winner);
+    }
+
+    #[test]
+    fn quadratic_delegate_cant_be_overridden() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let method = Some(VotingMethod::Quadratic { credits: 4 });
+        let msg = InitMsg { poll: String::from("How should we spend the budget?"), options: vec![String::from("benches"), String::from("trees")], allow_vote_override: true, method, ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Bert".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Allocate { poll_id: 0, votes: vec![OptionVotes { option: 0, votes: 2 }, OptionVotes { option: 1, votes: 2 }] };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // Anna's credits were spent by Bert
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Allocate { poll_id: 0, votes: vec![OptionVotes { option: 1, votes: 2 }] };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::DelegateAlreadyVoted { delegate: HumanAddr("Bert".to_string()) });
+    }
+
+    #[test]
+    fn participatory_budgeting() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let proposals = vec![String::from("pond"), String::from("orchard"), String::from("playground"), String::from("benches")];
+        let method = Some(VotingMethod::Budgeting { budget: 1000, costs: vec![500, 300, 400, 100] });
+        let msg = InitMsg { poll: String::from("Which proposals should the garden fund?"), options: proposals.clone(), method, ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let method = Some(VotingMethod::Budgeting { budget: 1000, costs: vec![500, 300] });
+        let msg = HandleMsg::CreatePoll { poll: String::from("Which proposals should the garden fund?"), options: proposals, duration: STANDARD_DURATION, early_results_allowed: true, topic: None, reveal_duration: None, quorum: None, threshold: None, method };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::InvalidPoll { reason: String::from("every proposal needs a cost") });
+
+        let approvals = vec![("Anna", vec![0, 1]), ("Bert", vec![0, 3]), ("Carl", vec![2, 1]), ("Dora", vec![0, 2]), ("Emil", vec![1])];
+        for (voter, options) in approvals {
+            let env = mock_env(voter, &coins(2, "token"));
+            let msg = HandleMsg::Approve { poll_id: 0, options };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        // The playground no longer fits once the pond and the orchard are funded, the benches still do
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 0 }).unwrap();
+        let value: ResultResponse = from_binary(&res).unwrap();
+        assert_eq!(Some(Funding { funded: vec![0, 1, 3], leftover_budget: 100 }), value.funding);
+        assert_eq!(Outcome::Passed, value.outcome);
+        assert_eq!(None, value.winner);
+    }
+
+    #[test]
+    fn score_voting() {
+        let mut deps = mock_dependencies(20, &coins(2, "token"));
+
+        let designs = vec![String::from("pond"), String::from("orchard"), String::from("playground")];
+        let method = Some(VotingMethod::Score { min: 1, max: 5 });
+        let msg = InitMsg { poll: String::from("How much do you like each garden design?"), options: designs.clone(), method, ..default_init_msg() };
+        let env = mock_env("creator", &coins(2, "token"));
+        let _res = init(&mut deps, env, msg).unwrap();
+
+        let env = mock_env("creator", &coins(2, "token"));
+        let method = Some(VotingMethod::Score { min: 5, max: 5 });
+        let msg = HandleMsg::CreatePoll { poll: String::from("How much do you like each garden design?"), options: designs, duration: STANDARD_DURATION, early_results_allowed: true, topic: None, reveal_duration: None, quorum: None, threshold: None, method };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::InvalidPoll { reason: String::from("scores must range from a minimum to a higher maximum of at most 100") });
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Score { poll_id: 0, scores: vec![5, 2] };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::MalformedBallot { reason: String::from("every option needs a score") });
+
+        let env = mock_env("Anna", &coins(2, "token"));
+        let msg = HandleMsg::Score { poll_id: 0, scores: vec![5, 2, 0] };
+        let res = handle(&mut deps, env, msg);
+        assert_contract_error(res, ContractError::MalformedBallot { reason: String::from("score 0 is out of range") });
+
+        // Bert's vote follows Anna's scores, which count twice
+        let env = mock_env("Bert", &coins(2, "token"));
+        let msg = HandleMsg::Delegate { poll_id: 0, delegate: HumanAddr("Anna".to_string()) };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let ballots = vec![("Anna", vec![5, 2, 1]), ("Carl", vec![1, 5, 4]), ("Dora", vec![1, 4, 5])];
+        for (voter, scores) in ballots {
+            let env = mock_env(voter, &coins(2, "token"));
+            let msg = HandleMsg::Score { poll_id: 0, scores };
+            let _res = handle(&mut deps, env, msg).unwrap();
+        }
+
+        let res = query(&deps, QueryMsg::GetTally { poll_id: 0 }).unwrap();
+        let value: TallyResponse = from_binary(&res).unwrap();
+        assert_eq!(vec![12, 13, 11], value.options.iter().map(|option| option.count).collect::<Vec<u64>>());
+
+        let mut env = mock_env("anyone", &coins(2, "token"));
+        env.block.time += STANDARD_DURATION + 1;
+        let msg = HandleMsg::CloseTally { poll_id: 0, limit: None };
+        let _res = handle(&mut deps, env, msg).unwrap();
+
+        let res = query(&deps, QueryMsg::GetResult { poll_id: 0 }).unwrap();
+        let value: ResultResponse = from_binary(&res).unwrap();
+        assert_eq!(Outcome::Passed, value.outcome);
+        assert_eq!(Some(1), value.winner);
+        let scores = vec![
+            OptionScores { average: Decimal::from_ratio(12u128, 4u128), distribution: vec![2, 0, 0, 0, 2] },
+            OptionScores { average: Decimal::from_ratio(13u128, 4u128), distribution: vec![0, 2, 0, 1, 1] },
+            OptionScores { average: Decimal::from_ratio(11u128, 4u128), distribution: vec![2, 0, 0, 1, 1] },
+        ];
+        assert_eq!(Some(scores), value.scores);
     }
 }
@@ -0,0 +1,134 @@
+use cosmwasm_std::{to_vec, HumanAddr, StdError};
+use serde::Serialize;
+use snafu::Snafu;
+
+// Every way a contract call can fail. Each error has a stable code, such that
+// frontends can tell users why their transaction failed.
+#[derive(Snafu, Debug, Clone, PartialEq)]
+pub enum ContractError {
+    #[snafu(display("Malformed ballot: {}", reason))]
+    MalformedBallot { reason: String },
+
+    #[snafu(display("Poll is closed"))]
+    PollClosed {},
+
+    #[snafu(display("Poll is still ongoing"))]
+    PollOngoing {},
+
+    #[snafu(display("Ballot was already cast"))]
+    AlreadyVoted {},
+
+    #[snafu(display("Results are sealed until the poll is closed"))]
+    ResultsSealed {},
+
+    #[snafu(display("Sender is not part of the electorate"))]
+    NotEligible {},
+
+    #[snafu(display("{} is not part of the electorate", delegate))]
+    IneligibleDelegate { delegate: HumanAddr },
+
+    #[snafu(display("Delegating to {} would create a cycle", delegate))]
+    DelegationCycle { delegate: HumanAddr },
+
+    #[snafu(display("Only the admin can do this"))]
+    NotAdmin {},
+
+    #[snafu(display("Electorate can't be changed: {}", reason))]
+    ElectorateLocked { reason: String },
+
+    #[snafu(display("Invalid poll: {}", reason))]
+    InvalidPoll { reason: String },
+
+    #[snafu(display("This poll has no mini public"))]
+    NoMiniPublic {},
+
+    #[snafu(display("Mini public was already drawn"))]
+    AlreadyDrawn {},
+
+    #[snafu(display("Seed does not match the commitment"))]
+    SeedMismatch {},
+
+    #[snafu(display("Delegation chain is longer than {} delegates", max_depth))]
+    DelegationTooDeep { max_depth: u32 },
+
+    #[snafu(display("Ballot was not delegated"))]
+    NotDelegated {},
+
+    #[snafu(display("Delegate {} has already voted", delegate))]
+    DelegateAlreadyVoted { delegate: HumanAddr },
+
+    #[snafu(display("There is no poll with id {}", poll_id))]
+    UnknownPoll { poll_id: u64 },
+
+    #[snafu(display("Wrong viewing key for this address or viewing key not set"))]
+    WrongViewingKey {},
+
+    #[snafu(display("Permit was rejected: {}", reason))]
+    PermitRejected { reason: String },
+
+    #[snafu(display("Votes can only be revealed between the end of voting and the end of the reveal window"))]
+    NotRevealing {},
+
+    #[snafu(display("Ballot has no commitment to reveal"))]
+    NoCommitment {},
+
+    #[snafu(display("Revealed vote does not match the commitment"))]
+    CommitmentMismatch {},
+
+    #[snafu(display("Ballot has neither a vote nor a delegate"))]
+    CorruptBallot {},
+
+    #[snafu(display("Standing delegates need a restricted electorate"))]
+    OpenElectorate {},
+}
+
+impl ContractError {
+    // Codes are part of the API, existing ones must never change
+    pub fn code(&self) -> u16 {
+        match self {
+            ContractError::MalformedBallot { .. } => 1,
+            ContractError::PollClosed { .. } => 2,
+            ContractError::PollOngoing { .. } => 3,
+            ContractError::AlreadyVoted { .. } => 4,
+            ContractError::ResultsSealed { .. } => 5,
+            ContractError::NotEligible { .. } => 6,
+            ContractError::IneligibleDelegate { .. } => 7,
+            ContractError::DelegationCycle { .. } => 8,
+            ContractError::NotAdmin { .. } => 9,
+            ContractError::ElectorateLocked { .. } => 10,
+            ContractError::InvalidPoll { .. } => 11,
+            ContractError::NoMiniPublic { .. } => 12,
+            ContractError::AlreadyDrawn { .. } => 13,
+            ContractError::SeedMismatch { .. } => 14,
+            ContractError::DelegationTooDeep { .. } => 15,
+            ContractError::NotDelegated { .. } => 16,
+            ContractError::DelegateAlreadyVoted { .. } => 17,
+            ContractError::UnknownPoll { .. } => 18,
+            ContractError::WrongViewingKey { .. } => 19,
+            ContractError::PermitRejected { .. } => 20,
+            ContractError::NotRevealing { .. } => 21,
+            ContractError::NoCommitment { .. } => 22,
+            ContractError::CommitmentMismatch { .. } => 23,
+            ContractError::CorruptBallot { .. } => 24,
+            ContractError::OpenElectorate { .. } => 25,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    code: u16,
+    message: String,
+}
+
+// Contract errors reach the client as generic errors, whose message is
+// a JSON object like {"code":6,"message":"Sender is not part of the electorate"}
+impl From<ContractError> for StdError {
+    fn from(err: ContractError) -> Self {
+        let body = ErrorBody { code: err.code(), message: err.to_string() };
+        match to_vec(&body) {
+            Ok(json) => StdError::generic_err(String::from_utf8_lossy(&json)),
+            Err(_) => StdError::generic_err(body.message),
+        }
+    }
+}
@@ -1,6 +1,9 @@
 pub mod contract;
+pub mod error;
 pub mod msg;
+pub mod permit;
 pub mod state;
+pub mod viewing_key;
 
 #[cfg(target_arch = "wasm32")]
 mod wasm {
@@ -1,36 +1,273 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use cosmwasm_std::{
-    HumanAddr,
+    HumanAddr, Binary, Decimal,
 };
 
+use crate::permit::Permit;
+use crate::state::{OptionVotes, Quorum, Threshold, Vote, VotingMethod};
+use crate::viewing_key::ViewingKey;
 
+
+// The poll given at init is the first one on the agenda, with id 0
 #[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub struct InitMsg {
     pub poll: String,
+    pub options: Vec<String>,
     pub duration: u64,
     pub early_results_allowed: bool,
+    // Addresses allowed to vote, anyone can vote if none are given
+    pub electorate: Option<Vec<HumanAddr>>,
+    // Electorate randomly drawn from a pool of candidates instead
+    pub mini_public: Option<MiniPublicInit>,
+    // Maximal number of delegates a vote can be passed through, defaults to 8
+    pub max_delegation_depth: Option<u32>,
+    // Delegators can override their delegate by voting directly
+    #[serde(default)]
+    pub allow_vote_override: bool,
+    // Subject of the poll, selecting which standing delegate applies
+    pub topic: Option<String>,
+    // Secret seed from which viewing keys are derived
+    pub prng_seed: Binary,
+    // Votes are committed then revealed within this many seconds after the end, see CommitVote
+    pub reveal_duration: Option<u64>,
+    // Rules deciding whether the poll passed, no quorum and a simple majority by default
+    pub quorum: Option<Quorum>,
+    pub threshold: Option<Threshold>,
+    // Single choice by default
+    pub method: Option<VotingMethod>,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "snake_case")]
-pub struct HandleMsg {
-    pub vote: Option<bool>,
-    pub delegate: Option<HumanAddr>,
+pub struct MiniPublicInit {
+    pub candidates: Vec<HumanAddr>,
+    pub sample_size: u32,
+    // sha256 of a secret seed, revealed when drawing the members
+    pub seed_commitment: Binary,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HandleMsg {
+    // Anyone allowed to vote, or the admin, puts a new poll on the agenda
+    CreatePoll {
+        poll: String,
+        options: Vec<String>,
+        duration: u64,
+        early_results_allowed: bool,
+        topic: Option<String>,
+        reveal_duration: Option<u64>,
+        quorum: Option<Quorum>,
+        threshold: Option<Threshold>,
+        method: Option<VotingMethod>,
+    },
+    // Vote for the option with the given index
+    Vote { poll_id: u64, option: u32 },
+    // In ranked-choice polls, vote with the indices of options by order of preference
+    Rank { poll_id: u64, ranking: Vec<u32> },
+    // In approval and budgeting polls, vote for every option one approves of
+    Approve { poll_id: u64, options: Vec<u32> },
+    // In quadratic polls, spread votes across options within one's credits
+    Allocate { poll_id: u64, votes: Vec<OptionVotes> },
+    // In score polls, give every option a score, in the order of the options
+    Score { poll_id: u64, scores: Vec<u32> },
+    // Take part without choosing, which counts for the quorum only
+    Abstain { poll_id: u64 },
+    // In commit-reveal polls, vote with sha256(option as 4 bytes big endian || salt)
+    CommitVote { poll_id: u64, commitment: Binary },
+    // Once voting ended, disclose the committed vote such that it is counted
+    RevealVote { poll_id: u64, option: u32, salt: Binary },
+    // Let someone else vote in one's place
+    Delegate { poll_id: u64, delegate: HumanAddr },
+    // Take back a delegation, as long as the final delegate hasn't voted
+    RevokeDelegation { poll_id: u64 },
+    // Let someone else vote in one's place in every poll one doesn't vote in,
+    // either on the given topic or by default
+    SetStandingDelegate { delegate: HumanAddr, topic: Option<String> },
+    ClearStandingDelegate { topic: Option<String> },
+    // Anyone, once the end of the tally has passed. Standing delegations are resolved
+    // `limit` (default 50, max 200) delegators at a time, until the tally is closed.
+    CloseTally { poll_id: u64, limit: Option<u32> },
+    // Keys to query one's own ballots, as in SNIP-20
+    CreateViewingKey { entropy: String },
+    SetViewingKey { key: String },
+    // Permits signed with this name are no longer accepted
+    RevokePermit { permit_name: String },
+    // Admin only, before anyone voted
+    AddElectorate { members: Vec<HumanAddr> },
+    RemoveElectorate { members: Vec<HumanAddr> },
+    // Admin only, reveals the seed committed to at init
+    DrawMiniPublic { seed: Binary },
 }
 
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
-    GetPoll {},
-    GetTally {},
+    GetPoll { poll_id: u64 },
+    GetTally { poll_id: u64 },
+    // Whether the poll passed, once its tally was closed
+    GetResult { poll_id: u64 },
+    // Polls by increasing id, at most `limit` (default 10, max 30) of them after `start_after`,
+    // among the next 100 polls
+    ListPolls {
+        status: Option<PollStatus>,
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    // Own standing delegates, authenticated with a viewing key
+    GetStandingDelegate { address: HumanAddr, key: String },
+    // Own ballot in the given poll, authenticated with a viewing key
+    GetMyBallot { poll_id: u64, address: HumanAddr, key: String },
+    GetMiniPublic {},
+    GetConfig {},
+    // Queries on behalf of the signer of the permit
+    WithPermit { permit: Permit, query: QueryWithPermit },
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum QueryWithPermit {
+    // Both need the ballot permission
+    GetMyBallot { poll_id: u64 },
+    GetStandingDelegate {},
+}
+
+// Queries don't know the current time, a poll is closed once its tally was closed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PollStatus {
+    Open,
+    Closed,
 }
 
 // We define a custom struct for each query response
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct PollResponse {
+    pub poll_id: u64,
+    pub poll: String,
+    pub options: Vec<String>,
+    pub topic: Option<String>,
+    pub init_timestamp: u64,
+    pub end_timestamp: u64,
+    pub reveal_end_timestamp: Option<u64>,
+    pub early_results_allowed: bool,
+    pub is_completed: bool,
+    pub quorum: Option<Quorum>,
+    pub threshold: Threshold,
+    pub method: VotingMethod,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ListPollsResponse {
+    pub polls: Vec<PollResponse>,
+    // Id to list on from as start_after, None once every poll was read
+    pub next_start_after: Option<u64>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct TallyResponse {
-    pub count: i32,
+    // Options in the order they were defined, with their current number of votes
+    pub options: Vec<OptionTally>,
+    pub abstentions: u64,
+    // Set by close_tally, queries don't know the current time
+    pub is_completed: bool,
+    // Time after which no more ballots are accepted, even while the tally isn't closed yet
+    pub end_timestamp: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OptionTally {
+    pub label: String,
+    pub count: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ResultResponse {
+    pub outcome: Outcome,
+    // Option that passed
+    pub winner: Option<u32>,
+    // Elimination rounds of ranked-choice polls, with the votes of each option
+    pub rounds: Vec<Vec<u64>>,
+    // Proposals funded by budgeting polls
+    pub funding: Option<Funding>,
+    // Averages and distribution of the scores of each option in score polls
+    pub scores: Option<Vec<OptionScores>>,
+    // Number of votes counted, abstentions included
+    pub turnout: u64,
+    // Only known when the electorate is restricted
+    pub electorate_size: Option<u64>,
+    // Approvals in approval polls, first preferences in ranked-choice polls,
+    // total scores in score polls
+    pub options: Vec<OptionTally>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Funding {
+    // Proposals by decreasing approvals
+    pub funded: Vec<u32>,
+    pub leftover_budget: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OptionScores {
+    pub average: Decimal,
+    // Number of votes giving each score, from the minimum to the maximum
+    pub distribution: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Passed,
+    // Quorum was reached, but no option reached the threshold
+    Rejected,
+    QuorumNotReached,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MyBallotResponse {
+    pub has_voted: bool,
+    pub vote: Option<Vote>,
+    pub delegate: Option<HumanAddr>,
+    // Own vote and the votes delegated to it
+    pub vote_value: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct StandingDelegateResponse {
+    // Default delegate, for topics without a delegate of their own
+    pub delegate: Option<HumanAddr>,
+    pub topics: Vec<TopicDelegate>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct TopicDelegate {
+    pub topic: String,
+    pub delegate: HumanAddr,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MiniPublicResponse {
+    pub candidates: Vec<HumanAddr>,
+    pub sample_size: u32,
+    pub seed_commitment: Binary,
+    // The following are only known once the draw took place
+    pub seed: Option<Binary>,
+    pub block_height: Option<u64>,
+    pub block_time: Option<u64>,
+    pub selected: Option<Vec<HumanAddr>>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConfigResponse {
+    pub admin: HumanAddr,
+    pub restricted_electorate: bool,
+    pub max_delegation_depth: u32,
+    pub allow_vote_override: bool,
+    pub poll_count: u64,
+    pub electorate_size: u64,
 }
 
 // success or failure response
@@ -51,12 +288,25 @@ pub enum HandleAnswer {
         status: ResponseStatus,
         /// execution description
         message: String,
+        // Code of the error when the ballot failed, see ContractError::code
+        error_code: Option<u16>,
         // New vote
         #[serde(skip_serializing_if = "Option::is_none")]
-        vote: Option<bool>,
+        vote: Option<Vote>,
         // Address of entity to which vote was delegated, called a delegate
         delegate: Option<HumanAddr>,
     },
+    // response from poll creation
+    CreatePoll {
+        /// success or failure
+        status: ResponseStatus,
+        /// id to refer to the poll in later messages
+        poll_id: u64,
+    },
+    // response from viewing key creation
+    CreateViewingKey {
+        key: ViewingKey,
+    },
     // generic status response
     Status {
         /// success or failure
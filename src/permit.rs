@@ -0,0 +1,166 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use cosmwasm_std::{to_vec, Binary, CanonicalAddr, HumanAddr, ReadonlyStorage, StdError, StdResult};
+use ripemd160::{Digest, Ripemd160};
+use secret_toolkit::crypto::secp256k1::{PublicKey, Signature};
+use secret_toolkit::crypto::sha_256;
+
+use crate::error::ContractError;
+use crate::state::is_permit_revoked;
+
+// Type of the public keys of Cosmos accounts
+const PUB_KEY_TYPE: &str = "tendermint/PubKeySecp256k1";
+
+// Off-chain authorization of queries, as in SNIP-24: the owner of an address signs
+// which contracts may be queried on its behalf, and which queries are allowed.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct Permit {
+    pub params: PermitParams,
+    pub signature: PermitSignature,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PermitParams {
+    // Contracts the permit is valid for, named like in SNIP-24 such that wallets can sign it
+    pub allowed_tokens: Vec<HumanAddr>,
+    // Name under which the permit can be revoked
+    pub permit_name: String,
+    pub chain_id: String,
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Permission {
+    // Own ballots
+    Ballot,
+    // Everything
+    Owner,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PermitSignature {
+    pub pub_key: PubKey,
+    pub signature: Binary,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub struct PubKey {
+    pub r#type: String,
+    // Compressed secp256k1 public key
+    pub value: Binary,
+}
+
+// Document actually signed by the wallet, an Amino sign doc with a single message.
+// Fields are in alphabetical order, as required for the JSON to be canonical.
+#[derive(Serialize)]
+struct SignDoc<'a> {
+    account_number: &'a str,
+    chain_id: &'a str,
+    fee: Fee<'a>,
+    memo: &'a str,
+    msgs: [PermitMsg<'a>; 1],
+    sequence: &'a str,
+}
+
+#[derive(Serialize)]
+struct Fee<'a> {
+    amount: [Coin<'a>; 1],
+    gas: &'a str,
+}
+
+#[derive(Serialize)]
+struct Coin<'a> {
+    amount: &'a str,
+    denom: &'a str,
+}
+
+#[derive(Serialize)]
+struct PermitMsg<'a> {
+    r#type: &'a str,
+    value: PermitContent<'a>,
+}
+
+#[derive(Serialize)]
+struct PermitContent<'a> {
+    allowed_tokens: &'a [HumanAddr],
+    permissions: &'a [Permission],
+    permit_name: &'a str,
+}
+
+impl Permit {
+    // Bytes the wallet signs
+    pub fn sign_bytes(&self) -> StdResult<Vec<u8>> {
+        to_vec(&SignDoc {
+            account_number: "0",
+            chain_id: &self.params.chain_id,
+            fee: Fee { amount: [Coin { amount: "0", denom: "uscrt" }], gas: "1" },
+            memo: "",
+            msgs: [PermitMsg {
+                r#type: "query_permit",
+                value: PermitContent {
+                    allowed_tokens: &self.params.allowed_tokens,
+                    permissions: &self.params.permissions,
+                    permit_name: &self.params.permit_name,
+                },
+            }],
+            sequence: "0",
+        })
+    }
+}
+
+/// Returns the address that signed the permit
+///
+/// The permit has to name the given contract, be signed for the given chain, allow the given
+/// permission, carry a valid signature, and not be revoked by its signer.
+///
+/// # Arguments
+///
+/// * `storage` - contract's storage, holding the revoked permits
+/// * `permit` - permit given with the query
+/// * `contract_address` - address of this contract
+/// * `chain_id` - id of the chain the contract runs on
+/// * `permission` - permission needed by the query
+pub fn validate<S: ReadonlyStorage>(storage: &S, permit: &Permit, contract_address: &HumanAddr,
+    chain_id: &str, permission: Permission) -> StdResult<CanonicalAddr> {
+
+    let params = &permit.params;
+    if !params.allowed_tokens.contains(contract_address) {
+        return Err(rejected(format!("permit doesn't apply to {}", contract_address)))
+    }
+    // A permit signed for another chain could be replayed on this one
+    if params.chain_id != chain_id {
+        return Err(rejected(format!("permit was signed for chain {}", params.chain_id)))
+    }
+    if !params.permissions.contains(&permission) && !params.permissions.contains(&Permission::Owner) {
+        return Err(rejected(String::from("permit doesn't allow this query")))
+    }
+
+    let pub_key = &permit.signature.pub_key;
+    if pub_key.r#type != PUB_KEY_TYPE {
+        return Err(rejected(format!("public key must be of type {}", PUB_KEY_TYPE)))
+    }
+    // Errors of the crypto library are reported like any other rejection
+    let public_key = PublicKey::parse(pub_key.value.as_slice())
+        .map_err(|_| rejected(String::from("malformed public key")))?;
+    let signature = Signature::parse_slice(permit.signature.signature.as_slice())
+        .map_err(|_| rejected(String::from("malformed signature")))?;
+    if !public_key.verify(&sha_256(&permit.sign_bytes()?), signature) {
+        return Err(rejected(String::from("signature is invalid")))
+    }
+
+    // Same derivation as for Cosmos accounts
+    let owner_raw = CanonicalAddr(Binary(Ripemd160::digest(&sha_256(&public_key.serialize_compressed())).to_vec()));
+    if is_permit_revoked(storage, &owner_raw, &params.permit_name) {
+        return Err(rejected(format!("permit {} was revoked", params.permit_name)))
+    }
+    Ok(owner_raw)
+}
+
+fn rejected(reason: String) -> StdError {
+    ContractError::PermitRejected { reason }.into()
+}
@@ -1,30 +1,243 @@
+use std::any::type_name;
+
 use schemars::JsonSchema;
-use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use cosmwasm_std::{
-    HumanAddr,
+    HumanAddr, CanonicalAddr, Binary, ReadonlyStorage, StdError, StdResult, Storage,
 };
+use cosmwasm_storage::{PrefixedStorage, ReadonlyPrefixedStorage};
+
+use crate::viewing_key::VIEWING_KEY_SIZE;
+
+// Namespaces of the contract's storage, every key lives in exactly one of them,
+// such that addresses can't collide with the keys of singletons.
+pub const CONFIG: &[u8] = b"config";
+pub const POLL: &[u8] = b"poll";
+pub const TALLY: &[u8] = b"tally";
+pub const MINI_PUBLIC: &[u8] = b"mini_public";
+pub const ELECTORATE: &[u8] = b"electorate";
+pub const BALLOTS: &[u8] = b"ballots";
+pub const STANDING_DELEGATES: &[u8] = b"standing_delegates";
+pub const STANDING_DELEGATORS: &[u8] = b"standing_delegators";
+pub const STANDING_DELEGATOR_IDS: &[u8] = b"standing_delegator_ids";
+pub const STANDING_DELEGATOR_COUNT: &[u8] = b"standing_delegator_count";
+pub const FREE_DELEGATOR_IDS: &[u8] = b"free_delegator_ids";
+pub const FREE_DELEGATOR_ID_COUNT: &[u8] = b"free_delegator_id_count";
+pub const VIEWING_KEYS: &[u8] = b"viewing_keys";
+pub const REVOKED_PERMITS: &[u8] = b"revoked_permits";
+pub const RANKINGS: &[u8] = b"rankings";
+pub const RANKING_IDS: &[u8] = b"ranking_ids";
+pub const RUNOFFS: &[u8] = b"runoffs";
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct Poll {
+    // Question asked to the electorate
+    pub question: String,
+    // Labels of the options, a vote is the index of one of them
+    pub options: Vec<String>,
+    // Subject of the poll, selecting which standing delegate applies
+    pub topic: Option<String>,
+    // Minimal participation for the result to be valid
+    pub quorum: Option<Quorum>,
+    // Share of the votes the leading option needs to pass
+    pub threshold: Threshold,
+    // How voters express their choice, and how the winner is found
+    pub method: VotingMethod,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum VotingMethod {
+    // Each vote goes to a single option
+    SingleChoice,
+    // Voters rank the options, the winner is found by instant runoff
+    RankedChoice,
+    // Voters approve any number of options, the most approved one wins
+    Approval,
+    // Voters spread votes across options, n votes costing n² of the credits each voter gets
+    Quadratic { credits: u64 },
+    // Options are proposals with a cost, the most approved ones are funded within the budget
+    Budgeting { budget: u64, costs: Vec<u64> },
+    // Voters give every option a score between min and max, the best average wins
+    Score { min: u32, max: u32 },
+}
 
-//use cosmwasm_std::{CanonicalAddr, Storage};
-//use cosmwasm_storage::{singleton, singleton_read, ReadonlySingleton, Singleton};
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Quorum {
+    // Minimal number of votes
+    Absolute { count: u64 },
+    // Minimal share of the electorate, which therefore has to be restricted
+    Fraction { numerator: u64, denominator: u64 },
+}
+
+impl Quorum {
+    pub fn is_reached(&self, turnout: u64, electorate_size: u64) -> bool {
+        match *self {
+            Quorum::Absolute { count } => turnout >= count,
+            Quorum::Fraction { numerator, denominator } =>
+                turnout as u128 * denominator as u128 >= numerator as u128 * electorate_size as u128,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Threshold {
+    // More than half of the votes
+    Majority,
+    // At least the given share of the votes, e.g. 2/3 for a supermajority
+    Fraction { numerator: u64, denominator: u64 },
+}
+
+impl Threshold {
+    pub fn is_reached(&self, votes: u64, total: u64) -> bool {
+        match *self {
+            Threshold::Majority => 2 * votes as u128 > total as u128,
+            Threshold::Fraction { numerator, denominator } =>
+                votes as u128 * denominator as u128 >= numerator as u128 * total as u128,
+        }
+    }
+}
 
 
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
 pub struct Tally {
-    // Number of votes in favor
-    pub yes: u64,
-    // Number of votes against
-    pub no: u64,
-    // List of addresses of voters
-    pub voters: HashSet<Vec<u8>>,   // FIXME I would have liked to make it a HashMap but for some reason I couldn't make it work yet
+    // Number of votes for each option, indexed like the options of the poll.
+    // In approval polls, counts hold the approvals of each option.
+    // In ranked-choice polls, counts hold first preferences.
+    // In score polls, counts hold the total score of each option.
+    pub counts: Vec<u64>,
+    // Number of votes that abstained, which count for the turnout only
+    pub abstentions: u64,
+    // Number of votes counted, abstentions included
+    pub turnout: u64,
+    // In ranked-choice polls, votes of each distinct ranking are stored apart for the runoff,
+    // under ids below this number
+    pub ranking_count: u64,
+    // In score polls, number of votes giving each score to each option, indexed by option then
+    // by score from 0 to the maximum. Empty in other polls.
+    pub scores: Vec<Vec<u64>>,
     // Time of beginning of vote
     pub init_timestamp: u64,
     // Time of end of vote
     pub end_timestamp: u64,
+    // For commit-reveal polls, time of end of the reveal window that follows the vote
+    pub reveal_end_timestamp: Option<u64>,
     // Defines whether current tally state shall be private until completed
     pub early_results_allowed: bool,
     // Completion status, if true, that tally can be queried
     pub is_completed: bool,
+    // Set once a ballot was cast, from then on the electorate is fixed until the tally is closed
+    pub voting_started: bool,
+    // Size of a restricted electorate when the tally was closed, which a quorum is a share of
+    pub electorate_size: Option<u64>,
+    // While the tally is being closed, number of standing delegators whose ballots were resolved
+    pub standing_cursor: u64,
+}
+
+impl Tally {
+    // Whether ballots can no longer be cast at the given time, reveals may still follow
+    pub fn voting_ended(&self, time: u64) -> bool {
+        self.is_completed || self.end_timestamp < time
+    }
+
+    // Whether the poll is over at the given time, even if the tally wasn't closed yet
+    pub fn is_over(&self, time: u64) -> bool {
+        self.is_completed || self.reveal_end_timestamp.unwrap_or(self.end_timestamp) < time
+    }
+
+    pub fn is_commit_reveal(&self) -> bool {
+        self.reveal_end_timestamp.is_some()
+    }
+
+    // Counts the given number of votes for the choice of a ballot
+    pub fn add_votes(&mut self, vote: &Vote, votes: u64) {
+        self.turnout += votes;
+        match vote {
+            Vote::Option(option) => self.counts[*option as usize] += votes,
+            Vote::Ranking(ranking) => self.counts[ranking[0] as usize] += votes,
+            Vote::Approval(options) => {
+                for option in options {
+                    self.counts[*option as usize] += votes;
+                }
+            }
+            // Votes already account for the credits of everyone the ballot stands for
+            Vote::Quadratic(allocation) => {
+                for option_votes in allocation {
+                    self.counts[option_votes.option as usize] += option_votes.votes;
+                }
+            }
+            Vote::Scores(scores) => {
+                for (option, &score) in scores.iter().enumerate() {
+                    self.counts[option] += score as u64 * votes;
+                    self.scores[option][score as usize] += votes;
+                }
+            }
+            Vote::Abstain => self.abstentions += votes,
+        }
+    }
+
+    pub fn remove_votes(&mut self, vote: &Vote, votes: u64) {
+        self.turnout -= votes;
+        match vote {
+            Vote::Option(option) => self.counts[*option as usize] -= votes,
+            Vote::Ranking(ranking) => self.counts[ranking[0] as usize] -= votes,
+            Vote::Approval(options) => {
+                for option in options {
+                    self.counts[*option as usize] -= votes;
+                }
+            }
+            Vote::Quadratic(allocation) => {
+                for option_votes in allocation {
+                    self.counts[option_votes.option as usize] -= option_votes.votes;
+                }
+            }
+            Vote::Scores(scores) => {
+                for (option, &score) in scores.iter().enumerate() {
+                    self.counts[option] -= score as u64 * votes;
+                    self.scores[option][score as usize] -= votes;
+                }
+            }
+            Vote::Abstain => self.abstentions -= votes,
+        }
+    }
+}
+
+// Choice of a ballot that was cast
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Vote {
+    // Index of the chosen option
+    Option(u32),
+    // Indices of options by order of preference, not all options have to be ranked
+    Ranking(Vec<u32>),
+    // Indices of the approved options, each of them gets the full vote
+    Approval(Vec<u32>),
+    // Votes given to each option, paid with the credits of the ballot
+    Quadratic(Vec<OptionVotes>),
+    // Score given to each option, indexed like the options of the poll
+    Scores(Vec<u32>),
+    Abstain,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct OptionVotes {
+    pub option: u32,
+    pub votes: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct RankedVotes {
+    pub ranking: Vec<u32>,
+    pub votes: u64,
+}
+
+// Elimination rounds of a ranked-choice poll, run when the tally is closed
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct Runoff {
+    // Votes of each option in each round, eliminated options have none
+    pub rounds: Vec<Vec<u64>>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
@@ -33,10 +246,290 @@ pub struct Ballot {
     pub has_voted: bool,
     // time of vote
     pub timestamp: u64,
-    // vote
-    pub vote: Option<bool>,
+    // vote, None as long as the ballot wasn't cast for a choice
+    pub vote: Option<Vote>,
     // allow liquid democracy
     pub delegate: Option<HumanAddr>,
-    // vote value (can be increased through transfered votes)
+    // vote value (can be increased through transfered votes).
+    // For a delegated ballot, this is the total of votes forwarded through it.
     pub vote_value: u64,
+    // In commit-reveal polls, hash of the vote and a salt, the vote is only set once revealed
+    pub commitment: Option<Binary>,
+    // Number of delegations on the longest chain leading to this ballot. It isn't lowered when
+    // a delegation is revoked, and merely bounds the chains delegating through this ballot.
+    pub incoming_depth: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct Config {
+    // Address allowed to manage the electorate
+    pub admin: CanonicalAddr,
+    // If true, only addresses stored in the electorate can vote
+    pub restricted_electorate: bool,
+    // Maximal number of delegates a vote can be passed through
+    pub max_delegation_depth: u32,
+    // If true, delegators can override their delegate by voting directly
+    pub allow_vote_override: bool,
+    // Number of polls created so far, which is also the id of the next poll
+    pub poll_count: u64,
+    // Polls below this id are all closed, the electorate is fixed while later ones are active
+    pub first_open_poll: u64,
+    // Number of members of a restricted electorate
+    pub electorate_size: u64,
+    // Hash of the seed given at init, from which viewing keys are derived
+    pub prng_seed: Vec<u8>,
+    // Queries don't know the contract's address, permits are checked against this one
+    pub contract_address: HumanAddr,
+    // Permits are only accepted when signed for this chain
+    pub chain_id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, JsonSchema)]
+pub struct StandingDelegates {
+    // Applies to polls without a topic, and to topics without a delegate of their own
+    pub default: Option<CanonicalAddr>,
+    // Delegates for polls on a given topic
+    pub topics: Vec<(String, CanonicalAddr)>,
+    // Time of the last change, standing delegates changed after a poll ended don't apply to it
+    pub updated: u64,
+}
+
+impl StandingDelegates {
+    // Delegate for polls on the given topic, falling back to the default one
+    pub fn for_topic(&self, topic: Option<&str>) -> Option<&CanonicalAddr> {
+        topic
+            .and_then(|topic| self.topics.iter().find(|(name, _)| name == topic))
+            .map(|(_, delegate)| delegate)
+            .or(self.default.as_ref())
+    }
+
+    // Sets or removes the delegate for the given topic, or the default one
+    pub fn set(&mut self, topic: Option<String>, delegate: Option<CanonicalAddr>) {
+        match topic {
+            None => self.default = delegate,
+            Some(topic) => {
+                self.topics.retain(|(name, _)| name != &topic);
+                if let Some(delegate) = delegate {
+                    self.topics.push((topic, delegate));
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.topics.is_empty()
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct MiniPublic {
+    // Pool of addresses the members are drawn from
+    pub candidates: Vec<CanonicalAddr>,
+    // Number of members to draw
+    pub sample_size: u32,
+    // sha256 of the seed the admin reveals to perform the draw
+    pub seed_commitment: Binary,
+    // Outcome of the draw, once it took place
+    pub draw: Option<Draw>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, JsonSchema)]
+pub struct Draw {
+    // Revealed seed
+    pub seed: Binary,
+    // Block data mixed into the randomness
+    pub block_height: u64,
+    pub block_time: u64,
+    // Members of the mini public
+    pub selected: Vec<CanonicalAddr>,
+}
+
+
+////////////////////////////////////// Storage ////////////////////////////////////
+
+pub fn save_config<S: Storage>(storage: &mut S, config: &Config) -> StdResult<()> {
+    save(storage, CONFIG, &[], config)
+}
+
+pub fn load_config<S: ReadonlyStorage>(storage: &S) -> StdResult<Config> {
+    load(storage, CONFIG, &[])
+}
+
+// Polls and their tallies are keyed by poll id
+pub fn save_poll<S: Storage>(storage: &mut S, poll_id: u64, poll: &Poll) -> StdResult<()> {
+    save(storage, POLL, &poll_id.to_be_bytes(), poll)
+}
+
+pub fn load_poll<S: ReadonlyStorage>(storage: &S, poll_id: u64) -> StdResult<Poll> {
+    load(storage, POLL, &poll_id.to_be_bytes())
+}
+
+pub fn save_tally<S: Storage>(storage: &mut S, poll_id: u64, tally: &Tally) -> StdResult<()> {
+    save(storage, TALLY, &poll_id.to_be_bytes(), tally)
+}
+
+pub fn may_load_tally<S: ReadonlyStorage>(storage: &S, poll_id: u64) -> StdResult<Option<Tally>> {
+    may_load(storage, TALLY, &poll_id.to_be_bytes())
+}
+
+pub fn save_mini_public<S: Storage>(storage: &mut S, mini_public: &MiniPublic) -> StdResult<()> {
+    save(storage, MINI_PUBLIC, &[], mini_public)
+}
+
+pub fn may_load_mini_public<S: ReadonlyStorage>(storage: &S) -> StdResult<Option<MiniPublic>> {
+    may_load(storage, MINI_PUBLIC, &[])
+}
+
+pub fn add_member<S: Storage>(storage: &mut S, member: &CanonicalAddr) -> StdResult<()> {
+    save(storage, ELECTORATE, member.as_slice(), &true)
+}
+
+pub fn remove_member<S: Storage>(storage: &mut S, member: &CanonicalAddr) {
+    PrefixedStorage::new(ELECTORATE, storage).remove(member.as_slice())
+}
+
+pub fn is_member<S: ReadonlyStorage>(storage: &S, address: &CanonicalAddr) -> bool {
+    ReadonlyPrefixedStorage::new(ELECTORATE, storage).get(address.as_slice()).is_some()
+}
+
+// Votes of ranked-choice polls are grouped by ranking, which are numbered in the order they first appear
+pub fn save_ranked_votes<S: Storage>(storage: &mut S, poll_id: u64, ranking_id: u64, ranked_votes: &RankedVotes) -> StdResult<()> {
+    save(storage, RANKINGS, &[poll_id.to_be_bytes(), ranking_id.to_be_bytes()].concat(), ranked_votes)
+}
+
+pub fn load_ranked_votes<S: ReadonlyStorage>(storage: &S, poll_id: u64, ranking_id: u64) -> StdResult<RankedVotes> {
+    load(storage, RANKINGS, &[poll_id.to_be_bytes(), ranking_id.to_be_bytes()].concat())
+}
+
+pub fn save_ranking_id<S: Storage>(storage: &mut S, poll_id: u64, ranking: &[u32], ranking_id: u64) -> StdResult<()> {
+    save(storage, RANKING_IDS, &ranking_key(poll_id, ranking), &ranking_id)
+}
+
+pub fn may_load_ranking_id<S: ReadonlyStorage>(storage: &S, poll_id: u64, ranking: &[u32]) -> StdResult<Option<u64>> {
+    may_load(storage, RANKING_IDS, &ranking_key(poll_id, ranking))
+}
+
+pub fn save_runoff<S: Storage>(storage: &mut S, poll_id: u64, runoff: &Runoff) -> StdResult<()> {
+    save(storage, RUNOFFS, &poll_id.to_be_bytes(), runoff)
+}
+
+pub fn may_load_runoff<S: ReadonlyStorage>(storage: &S, poll_id: u64) -> StdResult<Option<Runoff>> {
+    may_load(storage, RUNOFFS, &poll_id.to_be_bytes())
+}
+
+// Every voter has its own key in each poll, such that the cost of a ballot doesn't grow with turnout
+pub fn save_ballot<S: Storage>(storage: &mut S, poll_id: u64, voter: &CanonicalAddr, ballot: &Ballot) -> StdResult<()> {
+    save(storage, BALLOTS, &ballot_key(poll_id, voter), ballot)
+}
+
+pub fn may_load_ballot<S: ReadonlyStorage>(storage: &S, poll_id: u64, voter: &CanonicalAddr) -> StdResult<Option<Ballot>> {
+    may_load(storage, BALLOTS, &ballot_key(poll_id, voter))
+}
+
+pub fn load_ballot<S: ReadonlyStorage>(storage: &S, poll_id: u64, voter: &CanonicalAddr) -> StdResult<Ballot> {
+    load(storage, BALLOTS, &ballot_key(poll_id, voter))
+}
+
+// A standing delegate casts the ballot of the delegator in every poll the delegator doesn't vote in.
+// Delegators are also listed, such that their ballots can be resolved when a tally is closed.
+// Delegators are listed by id, such that tallies can be closed a batch of delegators at a time.
+// The id of a delegator who removes their standing delegates is given to the next new delegator.
+// Tallies are closed after their poll ended, and ignore delegators who changed their standing
+// delegates since: an id reused while a tally is being closed never counts a delegator twice.
+pub fn save_standing_delegates<S: Storage>(storage: &mut S, delegator: &CanonicalAddr, delegates: &StandingDelegates) -> StdResult<()> {
+    if may_load::<_, u64>(storage, STANDING_DELEGATOR_IDS, delegator.as_slice())?.is_none() {
+        let free_count: u64 = may_load(storage, FREE_DELEGATOR_ID_COUNT, &[])?.unwrap_or(0);
+        let delegator_id = if free_count > 0 {
+            save(storage, FREE_DELEGATOR_ID_COUNT, &[], &(free_count - 1))?;
+            load(storage, FREE_DELEGATOR_IDS, &(free_count - 1).to_be_bytes())?
+        } else {
+            let delegator_id = standing_delegator_count(storage)?;
+            save(storage, STANDING_DELEGATOR_COUNT, &[], &(delegator_id + 1))?;
+            delegator_id
+        };
+        save(storage, STANDING_DELEGATORS, &delegator_id.to_be_bytes(), delegator)?;
+        save(storage, STANDING_DELEGATOR_IDS, delegator.as_slice(), &delegator_id)?;
+    }
+    save(storage, STANDING_DELEGATES, delegator.as_slice(), delegates)
+}
+
+pub fn remove_standing_delegates<S: Storage>(storage: &mut S, delegator: &CanonicalAddr) -> StdResult<()> {
+    let delegator_id: u64 = match may_load(storage, STANDING_DELEGATOR_IDS, delegator.as_slice())? {
+        Some(delegator_id) => delegator_id,
+        None => return Ok(()),
+    };
+    PrefixedStorage::new(STANDING_DELEGATES, storage).remove(delegator.as_slice());
+    PrefixedStorage::new(STANDING_DELEGATOR_IDS, storage).remove(delegator.as_slice());
+    PrefixedStorage::new(STANDING_DELEGATORS, storage).remove(&delegator_id.to_be_bytes());
+
+    let free_count: u64 = may_load(storage, FREE_DELEGATOR_ID_COUNT, &[])?.unwrap_or(0);
+    save(storage, FREE_DELEGATOR_IDS, &free_count.to_be_bytes(), &delegator_id)?;
+    save(storage, FREE_DELEGATOR_ID_COUNT, &[], &(free_count + 1))
+}
+
+pub fn may_load_standing_delegates<S: ReadonlyStorage>(storage: &S, delegator: &CanonicalAddr) -> StdResult<Option<StandingDelegates>> {
+    may_load(storage, STANDING_DELEGATES, delegator.as_slice())
+}
+
+// Highest number of delegators there ever were at once, whose ids are below this number
+pub fn standing_delegator_count<S: ReadonlyStorage>(storage: &S) -> StdResult<u64> {
+    Ok(may_load(storage, STANDING_DELEGATOR_COUNT, &[])?.unwrap_or(0))
+}
+
+// Delegator with the given id, None if the id is free
+pub fn may_load_standing_delegator<S: ReadonlyStorage>(storage: &S, delegator_id: u64) -> StdResult<Option<CanonicalAddr>> {
+    may_load(storage, STANDING_DELEGATORS, &delegator_id.to_be_bytes())
+}
+
+// Only the hash of a viewing key is stored
+pub fn save_viewing_key<S: Storage>(storage: &mut S, owner: &CanonicalAddr, hashed_key: &[u8; VIEWING_KEY_SIZE]) -> StdResult<()> {
+    save(storage, VIEWING_KEYS, owner.as_slice(), hashed_key)
+}
+
+pub fn may_load_viewing_key<S: ReadonlyStorage>(storage: &S, owner: &CanonicalAddr) -> StdResult<Option<[u8; VIEWING_KEY_SIZE]>> {
+    may_load(storage, VIEWING_KEYS, owner.as_slice())
+}
+
+// Permits are revoked by name, canonical addresses have a fixed length such that keys can't collide
+pub fn save_revoked_permit<S: Storage>(storage: &mut S, owner: &CanonicalAddr, permit_name: &str) -> StdResult<()> {
+    save(storage, REVOKED_PERMITS, &[owner.as_slice(), permit_name.as_bytes()].concat(), &true)
+}
+
+pub fn is_permit_revoked<S: ReadonlyStorage>(storage: &S, owner: &CanonicalAddr, permit_name: &str) -> bool {
+    ReadonlyPrefixedStorage::new(REVOKED_PERMITS, storage)
+        .get(&[owner.as_slice(), permit_name.as_bytes()].concat())
+        .is_some()
+}
+
+// Poll id has a fixed length, such that keys of different polls can't collide
+fn ballot_key(poll_id: u64, voter: &CanonicalAddr) -> Vec<u8> {
+    [&poll_id.to_be_bytes()[..], voter.as_slice()].concat()
+}
+
+fn ranking_key(poll_id: u64, ranking: &[u32]) -> Vec<u8> {
+    let mut key = poll_id.to_be_bytes().to_vec();
+    for option in ranking {
+        key.extend_from_slice(&option.to_be_bytes());
+    }
+    key
+}
+
+fn save<S: Storage, T: Serialize>(storage: &mut S, namespace: &[u8], key: &[u8], value: &T) -> StdResult<()> {
+    let data = bincode2::serialize(value)
+        .map_err(|_err| StdError::generic_err(format!("Failed to serialize {}", type_name::<T>())))?;
+    PrefixedStorage::new(namespace, storage).set(key, &data);
+    Ok(())
+}
+
+fn may_load<S: ReadonlyStorage, T: DeserializeOwned>(storage: &S, namespace: &[u8], key: &[u8]) -> StdResult<Option<T>> {
+    match ReadonlyPrefixedStorage::new(namespace, storage).get(key) {
+        Some(data) => bincode2::deserialize(&data)
+            .map(Some)
+            .map_err(|_err| StdError::generic_err(format!("Failed to deserialize {}", type_name::<T>()))),
+        None => Ok(None),
+    }
+}
+
+fn load<S: ReadonlyStorage, T: DeserializeOwned>(storage: &S, namespace: &[u8], key: &[u8]) -> StdResult<T> {
+    may_load(storage, namespace, key)?.ok_or_else(|| StdError::not_found(type_name::<T>()))
 }
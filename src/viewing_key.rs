@@ -0,0 +1,47 @@
+use std::fmt;
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use cosmwasm_std::{Binary, Env};
+use secret_toolkit::crypto::{sha_256, Prng};
+
+pub const VIEWING_KEY_SIZE: usize = 32;
+const VIEWING_KEY_PREFIX: &str = "api_key_";
+
+// Lets an address query its own ballots without a transaction, as in SNIP-20.
+// Only the hash of a key is stored.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct ViewingKey(pub String);
+
+impl ViewingKey {
+    // Derives a new key from the contract's seed, the entropy given by the owner and the block data
+    pub fn new(env: &Env, seed: &[u8], entropy: &[u8]) -> Self {
+        let rng_entropy = [
+            &env.block.height.to_be_bytes()[..],
+            &env.block.time.to_be_bytes(),
+            env.message.sender.0.as_bytes(),
+            entropy,
+        ].concat();
+
+        let mut rng = Prng::new(seed, &rng_entropy);
+        let key = sha_256(&rng.rand_bytes());
+
+        Self(format!("{}{}", VIEWING_KEY_PREFIX, Binary(key.to_vec()).to_base64()))
+    }
+
+    pub fn to_hashed(&self) -> [u8; VIEWING_KEY_SIZE] {
+        sha_256(self.0.as_bytes())
+    }
+
+    // Compares in constant time, such that the stored hash can't be guessed byte by byte
+    pub fn check_viewing_key(&self, hashed: &[u8]) -> bool {
+        let mine = self.to_hashed();
+        mine.len() == hashed.len() && mine.iter().zip(hashed).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+    }
+}
+
+impl fmt::Display for ViewingKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}